@@ -0,0 +1,300 @@
+use crate::error::ViuResult;
+use crate::{print_to, printer_kind, BlockCanvas, BlockMode, Config, PrinterKind};
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, DynamicImage, Frame, ImageFormat};
+use std::io::{Cursor, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Longest time to sleep before checking whether playback was cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Frames of an animated image, which can be played in the terminal with any printer.
+///
+/// Every frame is redrawn in place of the previous one. Half blocks only redraw the cells which
+/// changed, see [BlockCanvas]. With the Kitty graphics protocol,
+/// [KittyPrinter::print_animation](crate::KittyPrinter::print_animation) lets the terminal play
+/// the animation instead, which is much lighter.
+/// ## Example
+/// ```no_run
+/// use viuer::{Animation, CancellationToken, Config};
+///
+/// let animation = Animation::from_file("img.gif").expect("Could not decode image.");
+/// let token = CancellationToken::new();
+///
+/// // stop the animation from another thread after 5 seconds
+/// let handle = token.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(5));
+///     handle.cancel();
+/// });
+///
+/// animation
+///     .play(&Config::default(), None, &token)
+///     .expect("Playing failed.");
+/// ```
+pub struct Animation {
+    frames: Vec<(DynamicImage, Duration)>,
+}
+
+impl Animation {
+    /// Create an animation from decoded frames.
+    pub fn new(frames: Vec<Frame>) -> Self {
+        let frames = frames
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+            })
+            .collect();
+        Animation { frames }
+    }
+
+    /// Decode all frames with an [AnimationDecoder].
+    pub fn from_decoder<'a>(decoder: impl AnimationDecoder<'a>) -> ViuResult<Self> {
+        Ok(Animation::new(decoder.into_frames().collect_frames()?))
+    }
+
+    /// Read a GIF or APNG file and decode all its frames. Other images become an animation with
+    /// a single frame.
+    pub fn from_file(filename: &str) -> ViuResult<Self> {
+        let file_content = std::fs::read(filename)?;
+
+        match image::guess_format(&file_content) {
+            Ok(ImageFormat::Gif) => {
+                Animation::from_decoder(GifDecoder::new(Cursor::new(&file_content))?)
+            }
+            Ok(ImageFormat::Png) => {
+                Animation::from_decoder(PngDecoder::new(Cursor::new(&file_content))?.apng())
+            }
+            _ => {
+                let img = image::load_from_memory(&file_content)?;
+                Ok(Animation {
+                    frames: vec![(img, Duration::from_millis(0))],
+                })
+            }
+        }
+    }
+
+    /// Number of frames in the animation.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the animation has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Play the animation on stdout. `loops` is the number of times the animation is played,
+    /// or `None` to play it until `token` is cancelled.
+    ///
+    /// Returns once the animation finished or was cancelled. The cursor is left as it would be
+    /// after printing a single frame with the same [Config]. Still images, and animations whose
+    /// frames have no delay, are printed once.
+    pub fn play(
+        &self,
+        config: &Config,
+        loops: Option<u32>,
+        token: &CancellationToken,
+    ) -> ViuResult {
+        self.play_to(&mut std::io::stdout(), config, loops, token)
+    }
+
+    /// Same as [Animation::play], but writes the output to any [Write] implementor instead of
+    /// stdout.
+    pub fn play_to(
+        &self,
+        writer: &mut impl Write,
+        config: &Config,
+        loops: Option<u32>,
+        token: &CancellationToken,
+    ) -> ViuResult {
+        if self.frames.is_empty() || token.is_cancelled() {
+            return Ok(());
+        }
+
+        // replaying frames without a delay would only keep the CPU busy
+        if self.frames.len() == 1 || self.frames.iter().all(|(_, delay)| delay.is_zero()) {
+            if let Some((img, _)) = self.frames.last() {
+                print_to(writer, img, config)?;
+            }
+            return Ok(());
+        }
+
+        // every frame is drawn over the previous one by returning to where it started
+        let frame_config = Config {
+            restore_cursor: true,
+            ..config.clone()
+        };
+
+        // half blocks are diffed against the previous frame, which avoids flicker
+        let mut canvas =
+            if printer_kind(config) == PrinterKind::Block && config.block_mode == BlockMode::Half {
+                Some(BlockCanvas::new())
+            } else {
+                None
+            };
+        let mut draw = |img: &DynamicImage, config: &Config| match canvas.as_mut() {
+            Some(canvas) => canvas.draw(&mut *writer, img, config),
+            None => print_to(&mut *writer, img, config),
+        };
+
+        let mut last = None;
+        let mut played = 0;
+        'playback: while !token.is_cancelled() && loops.is_none_or(|n| played < n) {
+            for (img, delay) in &self.frames {
+                if token.is_cancelled() {
+                    break 'playback;
+                }
+
+                let deadline = Instant::now() + *delay;
+                draw(img, &frame_config)?;
+                last = Some(img);
+
+                // sleep in short steps, so that cancelling takes effect quickly
+                while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                    if token.is_cancelled() || remaining.is_zero() {
+                        break;
+                    }
+                    std::thread::sleep(remaining.min(CANCEL_CHECK_INTERVAL));
+                }
+            }
+            played += 1;
+        }
+
+        // draw the final frame once more, leaving the cursor where the caller asked for
+        if let Some(img) = last {
+            if !config.restore_cursor {
+                draw(img, config)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Token which stops an [Animation] that is playing. Clones share the same state, so one can be
+/// moved to another thread to cancel the playback.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Stop the animations using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [CancellationToken::cancel] was called on this token or one of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorDepth;
+    use image::{Delay, Rgba, RgbaImage};
+
+    fn frame(color: [u8; 4]) -> Frame {
+        Frame::from_parts(
+            RgbaImage::from_pixel(1, 2, Rgba(color)),
+            0,
+            0,
+            Delay::from_numer_denom_ms(1, 1),
+        )
+    }
+
+    fn config() -> Config {
+        Config {
+            absolute_offset: false,
+            resize: false,
+            use_kitty: false,
+            use_iterm: false,
+            use_sixel: false,
+            color_depth: ColorDepth::Truecolor,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_play_loops() {
+        let animation = Animation::new(vec![frame([255, 0, 0, 255]), frame([0, 0, 255, 255])]);
+        let mut buf: Vec<u8> = Vec::new();
+        animation
+            .play_to(&mut buf, &config(), Some(2), &CancellationToken::new())
+            .unwrap();
+
+        let out = std::str::from_utf8(&buf).unwrap();
+        // two loops of both frames, each restoring the cursor. Drawing the last frame once more
+        // only moves the cursor below it, as none of its cells change.
+        assert_eq!(out.matches("\x1b8").count(), 4);
+        assert_eq!(out.matches("38;2;255;0;0").count(), 2);
+        assert_eq!(out.matches("38;2;0;0;255").count(), 2);
+    }
+
+    #[test]
+    fn test_play_diffs_blocks() {
+        let red = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let mut changed = red.clone();
+        changed.put_pixel(1, 1, Rgba([0, 0, 255, 255]));
+        let delay = Delay::from_numer_denom_ms(1, 1);
+        let animation = Animation::new(vec![
+            Frame::from_parts(red.clone(), 0, 0, delay),
+            Frame::from_parts(red, 0, 0, delay),
+            Frame::from_parts(changed, 0, 0, delay),
+        ]);
+
+        let mut buf: Vec<u8> = Vec::new();
+        animation
+            .play_to(&mut buf, &config(), Some(1), &CancellationToken::new())
+            .unwrap();
+
+        // both cells of the first frame, none of the repeated one, and one of the last one
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert_eq!(out.matches('\u{2584}').count(), 3);
+        assert_eq!(out.matches("38;2;0;0;255").count(), 1);
+    }
+
+    #[test]
+    fn test_play_once() {
+        // nothing cancels these, so they have to return on their own
+        let animation = Animation::new(vec![frame([255, 0, 0, 255])]);
+        let mut buf: Vec<u8> = Vec::new();
+        animation
+            .play_to(&mut buf, &config(), None, &CancellationToken::new())
+            .unwrap();
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert_eq!(out.matches("38;2;255;0;0").count(), 1);
+        assert!(!out.contains("\x1b8"));
+
+        let mut buf: Vec<u8> = Vec::new();
+        Animation::new(Vec::new())
+            .play_to(&mut buf, &config(), None, &CancellationToken::new())
+            .unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_play_cancelled() {
+        let animation = Animation::new(vec![frame([255, 0, 0, 255])]);
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+
+        let mut buf: Vec<u8> = Vec::new();
+        animation
+            .play_to(&mut buf, &config(), None, &token)
+            .unwrap();
+        assert!(buf.is_empty());
+    }
+}
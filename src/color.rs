@@ -0,0 +1,397 @@
+use ansi_colours::{ansi256_from_rgb, rgb_from_ansi256};
+use image::{DynamicImage, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use termcolor::Color;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// The amount of colors the terminal can display.
+pub enum ColorDepth {
+    /// 24-bit colors.
+    Truecolor,
+    /// The xterm 256 color palette.
+    Ansi256,
+    /// The 8 basic ANSI colors and their bright variants.
+    Ansi16,
+    /// The 8 basic ANSI colors.
+    Ansi8,
+    /// No colors at all.
+    Mono,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// Dithering applied to the image when its colors are reduced to a smaller palette.
+pub enum Dither {
+    /// No dithering, every pixel gets the closest color.
+    None,
+    /// Floyd-Steinberg error diffusion.
+    FloydSteinberg,
+    /// Atkinson error diffusion. Only part of the error is spread, which keeps more contrast.
+    Atkinson,
+    /// Ordered dithering with an 8x8 Bayer matrix. Does not make the pattern
+    /// change between similar frames.
+    Bayer,
+}
+
+// Error diffusion kernels as (dx, dy, weight)
+const FLOYD_STEINBERG: [(i64, i64, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: [(i64, i64, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// The VGA palette, which is what the Linux console uses for the basic 16 colors
+const ANSI16_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (170, 0, 0),
+    (0, 170, 0),
+    (170, 85, 0),
+    (0, 0, 170),
+    (170, 0, 170),
+    (0, 170, 170),
+    (170, 170, 170),
+    (85, 85, 85),
+    (255, 85, 85),
+    (85, 255, 85),
+    (255, 255, 85),
+    (85, 85, 255),
+    (255, 85, 255),
+    (85, 255, 255),
+    (255, 255, 255),
+];
+
+lazy_static! {
+    static ref ANSI16_LAB: Vec<[f32; 3]> = ANSI16_PALETTE.iter().map(|c| rgb_to_lab(*c)).collect();
+}
+
+// Convert a color to the closest one which can be displayed with the given depth.
+// Colors of the basic palettes are matched in the CIELAB space, in which the distance between
+// colors is close to the perceived difference.
+pub(crate) fn get_color(rgb: (u8, u8, u8), depth: ColorDepth) -> Color {
+    match depth {
+        ColorDepth::Truecolor => Color::Rgb(rgb.0, rgb.1, rgb.2),
+        ColorDepth::Ansi256 => Color::Ansi256(ansi256_from_rgb(rgb)),
+        ColorDepth::Ansi16 => ansi16_color(nearest_ansi16(rgb, 16)),
+        ColorDepth::Ansi8 => ansi16_color(nearest_ansi16(rgb, 8)),
+        ColorDepth::Mono => {
+            if rgb_to_lab(rgb)[0] < 50.0 {
+                Color::Black
+            } else {
+                Color::White
+            }
+        }
+    }
+}
+
+// The color from the palette of the given depth which get_color would pick
+fn quantize(rgb: (u8, u8, u8), depth: ColorDepth) -> (u8, u8, u8) {
+    match depth {
+        ColorDepth::Truecolor => rgb,
+        ColorDepth::Ansi256 => rgb_from_ansi256(ansi256_from_rgb(rgb)),
+        ColorDepth::Ansi16 => ANSI16_PALETTE[nearest_ansi16(rgb, 16) as usize],
+        ColorDepth::Ansi8 => ANSI16_PALETTE[nearest_ansi16(rgb, 8) as usize],
+        ColorDepth::Mono => {
+            if rgb_to_lab(rgb)[0] < 50.0 {
+                (0, 0, 0)
+            } else {
+                (255, 255, 255)
+            }
+        }
+    }
+}
+
+// Replace the colors of the image with colors from the palette of the given depth, dithering
+// the result. Transparent pixels are left untouched and do not receive any error.
+pub(crate) fn dither(img: &DynamicImage, depth: ColorDepth, method: Dither) -> DynamicImage {
+    // the distance between neighbouring colors in the palette, roughly
+    let spread = match depth {
+        ColorDepth::Truecolor => return DynamicImage::ImageRgba8(img.to_rgba8()),
+        ColorDepth::Ansi256 => 40.0,
+        ColorDepth::Ansi16 => 85.0,
+        ColorDepth::Ansi8 => 170.0,
+        ColorDepth::Mono => 255.0,
+    };
+
+    let out = diffuse(img.to_rgba8(), spread, method, 1, |rgb| {
+        quantize(rgb, depth)
+    });
+    DynamicImage::ImageRgba8(out)
+}
+
+// Replace the colors of the image with the closest colors of a palette, dithering the result.
+// Pixels with less alpha than `min_alpha` are not drawn, so they are left untouched and do not
+// receive any error. Without a palette, nothing is replaced.
+pub(crate) fn dither_to_palette(
+    img: &RgbaImage,
+    palette: &[[u8; 3]],
+    method: Dither,
+    min_alpha: u8,
+) -> RgbaImage {
+    if palette.is_empty() {
+        return img.clone();
+    }
+
+    // colors spread evenly in the RGB cube would be this far apart
+    let spread = 255.0 / (palette.len() as f32).cbrt();
+
+    let mut cache = HashMap::new();
+    diffuse(img.clone(), spread, method, min_alpha, |rgb| {
+        let [r, g, b] = palette[*cache
+            .entry(rgb)
+            .or_insert_with(|| closest_in_palette([rgb.0, rgb.1, rgb.2], palette))];
+        (r, g, b)
+    })
+}
+
+// Index of the palette color with the smallest euclidean distance to the color. Returns 0 if
+// the palette is empty.
+pub(crate) fn closest_in_palette(color: [u8; 3], palette: &[[u8; 3]]) -> usize {
+    let distance = |other: &[u8; 3]| -> i32 {
+        color
+            .iter()
+            .zip(other.iter())
+            .map(|(&a, &b)| (i32::from(a) - i32::from(b)).pow(2))
+            .sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, other)| distance(other))
+        .map_or(0, |(i, _)| i)
+}
+
+// Replace every pixel with at least `min_alpha` with the color picked by `nearest`, spreading
+// the difference to such pixels with the chosen method. `spread` is roughly the distance between
+// the colors that can be picked.
+fn diffuse(
+    mut out: RgbaImage,
+    spread: f32,
+    method: Dither,
+    min_alpha: u8,
+    mut nearest: impl FnMut((u8, u8, u8)) -> (u8, u8, u8),
+) -> RgbaImage {
+    let (width, height) = out.dimensions();
+
+    let kernel: &[(i64, i64, f32)] = match method {
+        Dither::None | Dither::Bayer => &[],
+        Dither::FloydSteinberg => &FLOYD_STEINBERG,
+        Dither::Atkinson => &ATKINSON,
+    };
+
+    // colors with the accumulated error, which can go out of the 0-255 range
+    let mut colors: Vec<[f32; 3]> = out
+        .pixels()
+        .map(|p| [f32::from(p[0]), f32::from(p[1]), f32::from(p[2])])
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize;
+            let Rgba([_, _, _, alpha]) = *out.get_pixel(x, y);
+            if alpha < min_alpha {
+                continue;
+            }
+
+            let mut color = colors[i];
+            if method == Dither::Bayer {
+                let threshold = f32::from(BAYER_8X8[(y % 8) as usize][(x % 8) as usize]);
+                let offset = (threshold + 0.5) / 64.0 - 0.5;
+                for c in color.iter_mut() {
+                    *c += offset * spread;
+                }
+            }
+
+            let clamp = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+            let new = nearest((clamp(color[0]), clamp(color[1]), clamp(color[2])));
+            out.put_pixel(x, y, Rgba([new.0, new.1, new.2, alpha]));
+
+            let error = [
+                color[0] - f32::from(new.0),
+                color[1] - f32::from(new.1),
+                color[2] - f32::from(new.2),
+            ];
+            for (dx, dy, weight) in kernel {
+                let (nx, ny) = (i64::from(x) + dx, i64::from(y) + dy);
+                if nx < 0 || nx >= i64::from(width) || ny >= i64::from(height) {
+                    continue;
+                }
+                let n = (ny as u32 * width + nx as u32) as usize;
+                for (c, e) in colors[n].iter_mut().zip(error.iter()) {
+                    *c += e * weight;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+// Index of the closest color among the first palette_size colors of the 16 color palette
+fn nearest_ansi16(rgb: (u8, u8, u8), palette_size: usize) -> u8 {
+    let lab = rgb_to_lab(rgb);
+    let distance = |other: &[f32; 3]| {
+        (0..3)
+            .map(|i| (lab[i] - other[i]) * (lab[i] - other[i]))
+            .sum::<f32>()
+    };
+
+    ANSI16_LAB[..palette_size]
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+// The basic colors are written with their own escape codes (e.g. 31 for red), because not all
+// terminals with 16 colors understand the 256 color codes. The bright variants have no such
+// codes in termcolor.
+fn ansi16_color(index: u8) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::White,
+        i => Color::Ansi256(i),
+    }
+}
+
+// Convert an sRGB color to CIELAB, using the D65 white point
+fn rgb_to_lab(rgb: (u8, u8, u8)) -> [f32; 3] {
+    fn linear(c: u8) -> f32 {
+        let c = f32::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+    fn f(t: f32) -> f32 {
+        if t > 0.008_856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    }
+
+    let (r, g, b) = (linear(rgb.0), linear(rgb.1), linear(rgb.2));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.950_47;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.088_83;
+
+    [
+        116.0 * f(y) - 16.0,
+        500.0 * (f(x) - f(y)),
+        200.0 * (f(y) - f(z)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_color() {
+        let orange = (255, 128, 0);
+        assert_eq!(
+            get_color(orange, ColorDepth::Truecolor),
+            Color::Rgb(255, 128, 0)
+        );
+        assert_eq!(get_color(orange, ColorDepth::Ansi256), Color::Ansi256(208));
+        assert_eq!(get_color(orange, ColorDepth::Ansi16), Color::Yellow);
+        assert_eq!(get_color(orange, ColorDepth::Mono), Color::White);
+
+        let light_red = (250, 80, 80);
+        assert_eq!(get_color(light_red, ColorDepth::Ansi16), Color::Ansi256(9));
+        assert_eq!(get_color(light_red, ColorDepth::Ansi8), Color::Red);
+    }
+
+    fn dithered_colors(method: Dither, depth: ColorDepth) -> Vec<Rgba<u8>> {
+        let img =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([128, 128, 128, 255])));
+        dither(&img, depth, method)
+            .to_rgba8()
+            .pixels()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_dither() {
+        let white = Rgba([255, 255, 255, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+
+        for method in [Dither::FloydSteinberg, Dither::Atkinson, Dither::Bayer].iter() {
+            let pixels = dithered_colors(*method, ColorDepth::Mono);
+            let white_count = pixels.iter().filter(|p| **p == white).count();
+            let black_count = pixels.iter().filter(|p| **p == black).count();
+            assert_eq!(white_count + black_count, 256);
+            // gray is in the middle, so about half of the pixels should be white
+            assert!(white_count > 96 && white_count < 160, "{:?}", method);
+        }
+
+        // without dithering every pixel gets the same color
+        let pixels = dithered_colors(Dither::None, ColorDepth::Mono);
+        assert!(pixels.iter().all(|p| *p == pixels[0]));
+
+        // truecolor is left alone
+        let pixels = dithered_colors(Dither::FloydSteinberg, ColorDepth::Truecolor);
+        assert!(pixels.iter().all(|p| *p == Rgba([128, 128, 128, 255])));
+    }
+
+    #[test]
+    fn test_dither_transparent() {
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([128, 128, 128, 255]));
+        img.put_pixel(1, 0, Rgba([10, 20, 30, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+
+        let out = dither(&img, ColorDepth::Ansi8, Dither::FloydSteinberg).to_rgba8();
+        assert_eq!(*out.get_pixel(1, 0), Rgba([10, 20, 30, 0]));
+    }
+
+    #[test]
+    fn test_dither_to_palette_undrawn() {
+        // the undrawn pixel is far from the palette, but its error is not spread to the others
+        let mut img = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 0, 255]));
+        img.put_pixel(0, 0, Rgba([255, 255, 255, 100]));
+
+        let out = dither_to_palette(&img, &[[0, 0, 0], [255, 0, 0]], Dither::FloydSteinberg, 128);
+        assert_eq!(*out.get_pixel(0, 0), Rgba([255, 255, 255, 100]));
+        assert_eq!(*out.get_pixel(1, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(2, 0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_nearest_ansi16() {
+        for (i, c) in ANSI16_PALETTE.iter().enumerate() {
+            assert_eq!(nearest_ansi16(*c, 16), i as u8);
+        }
+        assert_eq!(nearest_ansi16((20, 20, 30), 16), 0);
+        assert_eq!(nearest_ansi16((240, 240, 240), 8), 7);
+        assert_eq!(nearest_ansi16((0, 0, 200), 8), 4);
+    }
+}
@@ -1,11 +1,14 @@
+use crate::color::{ColorDepth, Dither};
+use crate::printer::{Align, BlockMode, FitMode, Quantizer, SizeUnit};
 use crate::utils;
 
+#[derive(Clone)]
 /// Configuration struct to customize printing behaviour.
 pub struct Config {
     /// [resize](crate::resize) the image before printing. Defaults to true.
     pub resize: bool,
     /// Enable true transparency instead of checkerboard background.
-    /// Available only for the block printer. Defaults to false.
+    /// Available only for the block and Sixel printers. Defaults to false.
     pub transparent: bool,
     /// Make the x and y offset be relative to the top left terminal corner.
     /// If false, the y offset is relative to the cursor's position.
@@ -15,6 +18,12 @@ pub struct Config {
     pub x: u16,
     /// Y offset. Can be negative only when `absolute_offset` is `false`. Defaults to 0.
     pub y: i16,
+    /// Horizontal alignment of the image in the width, or in the terminal if no width is given.
+    /// Defaults to [Align::Start].
+    pub align_x: Align,
+    /// Vertical alignment of the image in the height, or in the terminal if no height is given.
+    /// Defaults to [Align::Start].
+    pub align_y: Align,
     /// Take a note of cursor position before printing and restore it when finished.
     /// Defaults to false.
     pub restore_cursor: bool,
@@ -22,15 +31,61 @@ pub struct Config {
     pub width: Option<u32>,
     /// Optional image height. Defaults to None.
     pub height: Option<u32>,
-    /// Use truecolor if the terminal supports it. Defaults to true.
-    pub truecolor: bool,
+    /// Unit of the width and height. Defaults to [SizeUnit::Cells].
+    pub size_unit: SizeUnit,
+    /// How the image is fitted in the width and height, or in the terminal if neither is given.
+    /// Defaults to [FitMode::Stretch], which keeps the aspect ratio unless both are given.
+    pub fit_mode: FitMode,
+    /// The amount of colors used by the block, braille and ASCII printers.
+    /// Defaults to the color depth detected from `COLORTERM`, `TERM` and terminfo.
+    pub color_depth: ColorDepth,
+    /// Dithering used by the block printer when colors are reduced to the palette of
+    /// `color_depth`. Only applies to [BlockMode::Half], as the other modes average the pixels
+    /// of a cell into two colors. Has no effect with truecolor. Defaults to [Dither::None].
+    pub dither: Dither,
+    /// Glyphs used by the block printer. Defaults to [BlockMode::Half].
+    pub block_mode: BlockMode,
+    /// Use braille patterns instead of blocks when no graphics protocol is used.
+    /// Defaults to false.
+    pub use_braille: bool,
+    /// Pixels with luminance above this value are drawn as braille dots. Defaults to 127.
+    pub braille_threshold: u8,
+    /// Dither the image before deciding which braille dots to draw. Defaults to false.
+    pub braille_dither: bool,
+    /// Print characters from `ascii_ramp` instead of blocks when no graphics protocol is used.
+    /// This is also done if `color_depth` is [ColorDepth::Mono], in which case no escape sequences
+    /// are printed and offsets are made with whitespace. Defaults to false.
+    pub use_ascii: bool,
+    /// Characters used by the ASCII printer, ordered from darkest to brightest.
+    /// Defaults to `" .:-=+*#%@"`.
+    pub ascii_ramp: String,
+    /// Color the characters printed by the ASCII printer, if the terminal supports it.
+    /// Defaults to false.
+    pub ascii_color: bool,
+    /// Ratio of the height of a terminal cell to its width, used to keep the aspect ratio of
+    /// printed images. If None, it is detected from the terminal, or 2 if that fails.
+    /// Defaults to None.
+    pub cell_aspect_ratio: Option<f32>,
     /// Use Kitty protocol if the terminal supports it. Defaults to true.
     pub use_kitty: bool,
+    /// Compress image data sent with the Kitty protocol (`o=z`), which saves a lot of bandwidth
+    /// over remote connections. Defaults to true.
+    pub kitty_compress: bool,
+    /// Print Kitty images as Unicode placeholder characters (`U=1`), which Kitty replaces with
+    /// the image. Unlike regular placements, these survive scrolling and work inside multiplexers
+    /// like tmux. Images are limited to 297 columns and rows. Defaults to false.
+    pub kitty_placeholders: bool,
     /// Use iTerm protocol if the terminal supports it. Defaults to true.
     pub use_iterm: bool,
-    /// Use Sixel protocol if the terminal supports it. Defaults to true.
-    #[cfg(feature = "sixel")]
+    /// Use Sixel protocol if the terminal supports it. Images are encoded natively, or with
+    /// libsixel if the `sixel` feature is enabled. Defaults to true.
     pub use_sixel: bool,
+    /// Algorithm which picks the colors of Sixel images, which are limited to the color
+    /// registers of the terminal. Defaults to [Quantizer::MedianCut].
+    pub sixel_quantizer: Quantizer,
+    /// Dithering applied to Sixel images after their colors are picked.
+    /// Defaults to [Dither::FloydSteinberg].
+    pub sixel_dither: Dither,
 }
 
 impl std::default::Default for Config {
@@ -41,14 +96,30 @@ impl std::default::Default for Config {
             absolute_offset: true,
             x: 0,
             y: 0,
+            align_x: Align::Start,
+            align_y: Align::Start,
             restore_cursor: false,
             width: None,
             height: None,
-            truecolor: utils::truecolor_available(),
+            size_unit: SizeUnit::Cells,
+            fit_mode: FitMode::Stretch,
+            color_depth: utils::color_depth(),
+            dither: Dither::None,
+            block_mode: BlockMode::Half,
+            use_braille: false,
+            braille_threshold: 127,
+            braille_dither: false,
+            use_ascii: false,
+            ascii_ramp: " .:-=+*#%@".to_owned(),
+            ascii_color: false,
+            cell_aspect_ratio: None,
             use_kitty: true,
+            kitty_compress: true,
+            kitty_placeholders: false,
             use_iterm: true,
-            #[cfg(feature = "sixel")]
             use_sixel: true,
+            sixel_quantizer: Quantizer::MedianCut,
+            sixel_dither: Dither::FloydSteinberg,
         }
     }
 }
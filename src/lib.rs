@@ -29,19 +29,25 @@
 use crossterm::execute;
 use image::DynamicImage;
 use printer::Printer;
+use std::io::Write;
 
+mod animation;
+mod color;
 mod config;
 mod error;
 mod printer;
 mod utils;
 
+pub use animation::{Animation, CancellationToken};
+pub use color::{ColorDepth, Dither};
 pub use config::Config;
 pub use error::{ViuError, ViuResult};
-pub use printer::{get_kitty_support, is_iterm_supported, resize, KittySupport};
-pub use utils::terminal_size;
-
-#[cfg(feature = "sixel")]
-pub use printer::is_sixel_supported;
+pub use printer::{
+    clear_kitty, find_best_fit, get_kitty_support, is_iterm_supported, is_sixel_supported,
+    print_kitty, resize, Align, BlockCanvas, BlockMode, FitMode, KittyClear, KittyDelete,
+    KittyImage, KittyPrinter, KittySupport, Placement, Quantizer, SizeUnit,
+};
+pub use utils::{cell_size, terminal_size};
 
 /// Default printing method. Uses either iTerm or Kitty graphics protocol, if supported,
 /// and half blocks otherwise.
@@ -67,17 +73,35 @@ pub use printer::is_sixel_supported;
 /// print(&img, &Config::default()).expect("Image printing failed.");
 /// ```
 pub fn print(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-    let mut stdout = std::io::stdout();
+    print_to(&mut std::io::stdout(), img, config)
+}
+
+/// Same as [print], but writes the output to any [Write] implementor instead of stdout.
+///
+/// Terminal capabilities are still detected by querying the terminal attached to stdout.
+/// ## Example
+/// ```no_run
+/// use viuer::{Config, print_to};
+///
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let mut buf: Vec<u8> = Vec::new();
+/// print_to(&mut buf, &img, &Config::default()).expect("Image printing failed.");
+/// ```
+pub fn print_to(
+    writer: &mut impl Write,
+    img: &DynamicImage,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
     if config.restore_cursor {
-        execute!(&mut stdout, crossterm::cursor::SavePosition)?;
+        execute!(writer, crossterm::cursor::SavePosition)?;
     }
 
     let printer = choose_printer(config);
 
-    let (w, h) = printer.print(img, config)?;
+    let (w, h) = printer.print(writer, &printer::fill_box(img, config), config)?;
 
     if config.restore_cursor {
-        execute!(&mut stdout, crossterm::cursor::RestorePosition)?;
+        execute!(writer, crossterm::cursor::RestorePosition)?;
     };
 
     Ok((w, h))
@@ -98,34 +122,186 @@ pub fn print(img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
 /// print_from_file("img.jpg", &conf).expect("Image printing failed.");
 /// ```
 pub fn print_from_file(filename: &str, config: &Config) -> ViuResult<(u32, u32)> {
-    let mut stdout = std::io::stdout();
+    print_from_file_to(&mut std::io::stdout(), filename, config)
+}
+
+/// Same as [print_from_file], but writes the output to any [Write] implementor instead of stdout.
+pub fn print_from_file_to(
+    writer: &mut impl Write,
+    filename: &str,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    // images which are cropped or padded have to be decoded first
+    if matches!(config.fit_mode, FitMode::Cover | FitMode::Fill) {
+        let img = image::open(filename)?;
+        return print_to(writer, &img, config);
+    }
+
     if config.restore_cursor {
-        execute!(&mut stdout, crossterm::cursor::SavePosition)?;
+        execute!(writer, crossterm::cursor::SavePosition)?;
     }
 
     let printer = choose_printer(config);
 
-    let (w, h) = printer.print_from_file(filename, config)?;
+    let (w, h) = printer.print_from_file(writer, filename, config)?;
 
     if config.restore_cursor {
-        execute!(&mut stdout, crossterm::cursor::RestorePosition)?;
+        execute!(writer, crossterm::cursor::RestorePosition)?;
     };
 
     Ok((w, h))
 }
 
+/// The output of [render]: everything [print] would write to the terminal, and the space it occupies.
+pub struct Rendered {
+    /// Escape sequences and characters which display the image when written to the terminal.
+    pub data: Vec<u8>,
+    /// Width of the image in terminal cells.
+    pub width: u32,
+    /// Height of the image in terminal cells.
+    pub height: u32,
+}
+
+/// Render an image to the bytes [print] would emit, instead of writing them to stdout.
+///
+/// Useful when the output has to be embedded in a frame composed by another UI. The printer is
+/// chosen the same way as in [print], and offsets from the [Config] are part of the output.
+///
+/// Choosing the printer can still query the terminal, by writing to stdout and reading the
+/// response from stdin, the first time support for a graphics protocol or the cell size is
+/// needed. Call it once before the UI takes over the terminal, or turn off the protocols that
+/// are not wanted in the [Config].
+/// ## Example
+/// ```no_run
+/// use std::io::Write;
+/// use viuer::{Config, render};
+///
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let rendered = render(&img, &Config::default()).expect("Image rendering failed.");
+/// println!("Image takes {}x{} cells", rendered.width, rendered.height);
+/// std::io::stdout().write_all(&rendered.data).unwrap();
+/// ```
+pub fn render(img: &DynamicImage, config: &Config) -> ViuResult<Rendered> {
+    let mut data = Vec::new();
+    let (width, height) = print_to(&mut data, img, config)?;
+
+    Ok(Rendered {
+        data,
+        width,
+        height,
+    })
+}
+
 // Choose the appropriate printer to use based on user config and availability
 fn choose_printer(config: &Config) -> Box<dyn Printer> {
-    #[cfg(feature = "sixel")]
-    if config.use_sixel && is_sixel_supported() {
-        return Box::new(printer::SixelPrinter {});
+    match printer_kind(config) {
+        PrinterKind::Iterm => Box::new(printer::iTermPrinter {}),
+        PrinterKind::Kitty => Box::new(printer::KittyPrinter {}),
+        PrinterKind::Sixel => Box::new(printer::SixelPrinter {}),
+        PrinterKind::Ascii => Box::new(printer::AsciiPrinter {}),
+        PrinterKind::Braille => Box::new(printer::BraillePrinter {}),
+        PrinterKind::Block => Box::new(printer::BlockPrinter {}),
     }
+}
+
+// The kind of printer that is used to print images with the config
+pub(crate) fn printer_kind(config: &Config) -> PrinterKind {
+    pick_printer(
+        config,
+        is_iterm_supported,
+        || get_kitty_support() != KittySupport::None,
+        is_sixel_supported,
+    )
+}
+
+#[derive(PartialEq, Debug)]
+pub(crate) enum PrinterKind {
+    Iterm,
+    Kitty,
+    Sixel,
+    Ascii,
+    Braille,
+    Block,
+}
 
-    if config.use_iterm && is_iterm_supported() {
-        Box::new(printer::iTermPrinter {})
-    } else if config.use_kitty && get_kitty_support() != KittySupport::None {
-        Box::new(printer::KittyPrinter {})
+// Pick a printer, checking the support for graphics protocols only when it matters. Native
+// protocols come first, as terminals which have one may list Sixel support as well.
+fn pick_printer(
+    config: &Config,
+    iterm: impl FnOnce() -> bool,
+    kitty: impl FnOnce() -> bool,
+    sixel: impl FnOnce() -> bool,
+) -> PrinterKind {
+    if config.use_iterm && iterm() {
+        PrinterKind::Iterm
+    } else if config.use_kitty && kitty() {
+        PrinterKind::Kitty
+    } else if config.use_sixel && sixel() {
+        PrinterKind::Sixel
+    } else if config.use_ascii || config.color_depth == ColorDepth::Mono {
+        PrinterKind::Ascii
+    } else if config.use_braille {
+        PrinterKind::Braille
     } else {
-        Box::new(printer::BlockPrinter {})
+        PrinterKind::Block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_block() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 6));
+        let config = Config {
+            absolute_offset: false,
+            use_kitty: false,
+            use_iterm: false,
+            use_sixel: false,
+            ..Default::default()
+        };
+
+        let rendered = render(&img, &config).unwrap();
+        assert_eq!(rendered.width, 20);
+        assert_eq!(rendered.height, 3);
+        assert!(!rendered.data.is_empty());
+    }
+
+    #[test]
+    fn test_pick_printer_order() {
+        let config = Config {
+            color_depth: ColorDepth::Truecolor,
+            ..Default::default()
+        };
+        let unused = || -> bool { panic!("support should not be checked") };
+
+        // terminals with a native protocol are not asked about Sixel
+        assert_eq!(
+            pick_printer(&config, || true, unused, unused),
+            PrinterKind::Iterm
+        );
+        assert_eq!(
+            pick_printer(&config, || false, || true, unused),
+            PrinterKind::Kitty
+        );
+        assert_eq!(
+            pick_printer(&config, || false, || false, || true),
+            PrinterKind::Sixel
+        );
+        assert_eq!(
+            pick_printer(&config, || false, || false, || false),
+            PrinterKind::Block
+        );
+
+        let config = Config {
+            use_iterm: false,
+            use_kitty: false,
+            ..config
+        };
+        assert_eq!(
+            pick_printer(&config, unused, unused, || true),
+            PrinterKind::Sixel
+        );
     }
 }
@@ -0,0 +1,110 @@
+use crate::color::{get_color, ColorDepth};
+use crate::error::ViuResult;
+use crate::printer::block::{adjust_y_offset, print_buffer};
+use crate::printer::{align, Printer};
+use crate::Config;
+
+use image::{DynamicImage, GenericImageView};
+use std::io::Write;
+use termcolor::{Buffer, ColorSpec, WriteColor};
+
+pub struct AsciiPrinter {}
+
+impl Printer for AsciiPrinter {
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        // resize the image so that it fits in the constraints, if any
+        let resized_img;
+        let img = if config.resize {
+            resized_img = super::resize_for_cells(img, config, (1, 1));
+            &resized_img
+        } else {
+            img
+        };
+        let config = &align(config, img.dimensions());
+
+        // without color support, no escape sequences are written at all
+        let colored = config.ascii_color && config.color_depth != ColorDepth::Mono;
+        let mut out_buffer = if colored {
+            let mut buffer = Buffer::ansi();
+            adjust_y_offset(&mut buffer, config)?;
+            buffer
+        } else {
+            let mut buffer = Buffer::no_color();
+            for _ in 0..config.y.max(0) {
+                writeln!(buffer)?;
+            }
+            buffer
+        };
+
+        let (width, height) = img.dimensions();
+        let ramp: Vec<char> = config.ascii_ramp.chars().collect();
+
+        for y in 0..height {
+            // spaces are used for the x offset, so that it works in dumb terminals too
+            write!(out_buffer, "{:1$}", "", config.x as usize)?;
+
+            for x in 0..width {
+                let pixel = img.get_pixel(x, y);
+                // transparent pixels and empty ramps are printed as spaces
+                let c = if pixel[3] == 0 || ramp.is_empty() {
+                    ' '
+                } else {
+                    let luma = 0.299 * f32::from(pixel[0])
+                        + 0.587 * f32::from(pixel[1])
+                        + 0.114 * f32::from(pixel[2]);
+                    let i = (luma / 255.0 * (ramp.len() - 1) as f32).round() as usize;
+                    ramp[i.min(ramp.len() - 1)]
+                };
+
+                let mut spec = ColorSpec::new();
+                spec.set_fg(Some(get_color(
+                    (pixel[0], pixel[1], pixel[2]),
+                    config.color_depth,
+                )));
+                out_buffer.set_color(&spec)?;
+                write!(out_buffer, "{}", c)?;
+            }
+
+            out_buffer.reset()?;
+            writeln!(out_buffer)?;
+            print_buffer(stdout, &mut out_buffer)?;
+        }
+
+        Ok((width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_ascii_printer_plain() {
+        let mut img = image::RgbaImage::from_pixel(3, 2, Rgba([255, 255, 255, 255]));
+        img.put_pixel(1, 0, Rgba([0, 0, 0, 255]));
+        img.put_pixel(2, 0, Rgba([128, 128, 128, 255]));
+        img.put_pixel(0, 1, Rgba([0, 0, 0, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+
+        let config = Config {
+            x: 2,
+            y: 1,
+            absolute_offset: false,
+            resize: false,
+            ascii_ramp: " -#".to_owned(),
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        let (w, h) = AsciiPrinter {}.print(&mut buf, &img, &config).unwrap();
+
+        assert_eq!(w, 3);
+        assert_eq!(h, 2);
+        assert_eq!(std::str::from_utf8(&buf).unwrap(), "\n  # -\n   ##\n");
+    }
+}
@@ -1,154 +1,523 @@
+use crate::color::{dither, get_color, ColorDepth, Dither};
 use crate::error::{ViuError, ViuResult};
-use crate::printer::Printer;
+use crate::printer::{align, fill_box, Printer};
 use crate::Config;
 
-use ansi_colours::ansi256_from_rgb;
 use image::{DynamicImage, GenericImageView, Rgba};
+use std::borrow::Cow;
 use std::io::Write;
-use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use termcolor::{Buffer, Color, ColorSpec, WriteColor};
 
-use crossterm::cursor::{MoveRight, MoveTo, MoveToPreviousLine};
+use crossterm::cursor::{MoveRight, MoveTo, MoveToPreviousLine, RestorePosition, SavePosition};
 use crossterm::execute;
 
 const UPPER_HALF_BLOCK: &str = "\u{2580}";
 const LOWER_HALF_BLOCK: &str = "\u{2584}";
 
+// Glyphs indexed by the mask of pixels they draw with the foreground color,
+// see BlockMode::glyph
+const HALF_BLOCKS: [char; 4] = [' ', '\u{2580}', '\u{2584}', '\u{2588}'];
+const QUADRANT_BLOCKS: [char; 16] = [
+    ' ', '\u{2598}', '\u{259D}', '\u{2580}', '\u{2596}', '\u{258C}', '\u{259E}', '\u{259B}',
+    '\u{2597}', '\u{259A}', '\u{2590}', '\u{259C}', '\u{2584}', '\u{2599}', '\u{259F}', '\u{2588}',
+];
+
+// Sextants (Unicode 13) and octants (Unicode 16) are encoded in the order of their masks, except
+// for the masks that already had a glyph before. These are listed here, sorted by mask.
+const SEXTANT_BASE: u32 = 0x1FB00;
+const SEXTANT_EXCEPTIONS: [(u8, char); 4] = [
+    (0b00_0000, ' '),
+    (0b01_0101, '\u{258C}'),
+    (0b10_1010, '\u{2590}'),
+    (0b11_1111, '\u{2588}'),
+];
+const OCTANT_BASE: u32 = 0x1CD00;
+const OCTANT_EXCEPTIONS: [(u8, char); 26] = [
+    (0x00, ' '),
+    (0x01, '\u{1CEA8}'),
+    (0x02, '\u{1CEAB}'),
+    (0x03, '\u{1FB82}'),
+    (0x05, '\u{2598}'),
+    (0x0A, '\u{259D}'),
+    (0x0F, '\u{2580}'),
+    (0x14, '\u{1FBE6}'),
+    (0x28, '\u{1FBE7}'),
+    (0x3F, '\u{1FB85}'),
+    (0x40, '\u{1CEA3}'),
+    (0x50, '\u{2596}'),
+    (0x55, '\u{258C}'),
+    (0x5A, '\u{259E}'),
+    (0x5F, '\u{259B}'),
+    (0x80, '\u{1CEA0}'),
+    (0xA0, '\u{2597}'),
+    (0xA5, '\u{259A}'),
+    (0xAA, '\u{2590}'),
+    (0xAF, '\u{259C}'),
+    (0xC0, '\u{2582}'),
+    (0xF0, '\u{2584}'),
+    (0xF5, '\u{2599}'),
+    (0xFA, '\u{259F}'),
+    (0xFC, '\u{2586}'),
+    (0xFF, '\u{2588}'),
+];
+
 const CHECKERBOARD_BACKGROUND_LIGHT: (u8, u8, u8) = (153, 153, 153);
 const CHECKERBOARD_BACKGROUND_DARK: (u8, u8, u8) = (102, 102, 102);
 
 pub struct BlockPrinter {}
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// The set of glyphs the block printer uses to draw pixels.
+pub enum BlockMode {
+    /// Half blocks (▀ and ▄), displaying 1x2 pixels in a single cell.
+    Half,
+    /// Quadrant blocks (▖, ▝, ▚, etc.), displaying 2x2 pixels in a single cell.
+    Quarter,
+    /// Sextants from Unicode 13, displaying 2x3 pixels in a single cell.
+    /// Requires a font which supports the "Symbols for Legacy Computing" block.
+    Sextant,
+    /// Octants from Unicode 16, displaying 2x4 pixels in a single cell.
+    /// Requires a font which supports the "Symbols for Legacy Computing Supplement" block.
+    Octant,
+}
+
+impl BlockMode {
+    // Amount of pixels (columns, rows) displayed in a single terminal cell
+    fn cell_pixels(self) -> (u32, u32) {
+        match self {
+            BlockMode::Half => (1, 2),
+            BlockMode::Quarter => (2, 2),
+            BlockMode::Sextant => (2, 3),
+            BlockMode::Octant => (2, 4),
+        }
+    }
+
+    // Glyph which draws the pixels set in the mask with the foreground color. Pixels are
+    // numbered left to right, top to bottom, starting from the least significant bit.
+    fn glyph(self, mask: u8) -> char {
+        match self {
+            BlockMode::Half => HALF_BLOCKS[mask as usize],
+            BlockMode::Quarter => QUADRANT_BLOCKS[mask as usize],
+            BlockMode::Sextant => legacy_glyph(mask, SEXTANT_BASE, &SEXTANT_EXCEPTIONS),
+            BlockMode::Octant => legacy_glyph(mask, OCTANT_BASE, &OCTANT_EXCEPTIONS),
+        }
+    }
+}
+
+// Find the glyph for a mask in a block of characters which skips the listed exceptions
+fn legacy_glyph(mask: u8, base: u32, exceptions: &[(u8, char)]) -> char {
+    match exceptions.binary_search_by_key(&mask, |(m, _)| *m) {
+        Ok(i) => exceptions[i].1,
+        // i is the amount of exceptions before the mask
+        Err(i) => std::char::from_u32(base + u32::from(mask) - i as u32).unwrap_or(' '),
+    }
+}
+
 impl Printer for BlockPrinter {
-    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-        // there are two types of buffers in this function:
-        // - stdout: Buffer, which is from termcolor crate. Used to buffer all writing
-        //   required to print a single image or frame. Flushed on every line
-        // - row_buffer: Vec<ColorSpec>, which stores back- and foreground colors for a
-        //   row of terminal cells. When flushed, its output goes into out_buffer.
-        // They are both flushed on every terminal line (i.e 2 pixel rows)
-        let stdout = BufferWriter::stdout(ColorChoice::Always);
-        let mut out_buffer = stdout.buffer();
-
-        // adjust y offset
-        if config.absolute_offset {
-            if config.y >= 0 {
-                // If absolute_offset, move to (0,y).
-                execute!(out_buffer, MoveTo(0, config.y as u16))?;
-            } else {
-                //Negative values do not make sense.
-                return Err(ViuError::InvalidConfiguration(
-                    "absolute_offset is true but y offset is negative".to_owned(),
-                ));
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        let mut out_buffer = Buffer::ansi();
+
+        let img = prepare_image(img, config, config.block_mode);
+        let config = &align(config, cells(&img, config.block_mode.cell_pixels()));
+        adjust_y_offset(&mut out_buffer, config)?;
+
+        match config.block_mode {
+            BlockMode::Half => print_half_blocks(stdout, &mut out_buffer, &img, config),
+            mode => print_cells(stdout, &mut out_buffer, &img, config, mode),
+        }
+    }
+}
+
+// Size in cells of an image printed with cell_pixels (columns, rows) in every cell
+pub(super) fn cells(img: &DynamicImage, cell_pixels: (u32, u32)) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    (
+        width.div_ceil(cell_pixels.0),
+        height.div_ceil(cell_pixels.1),
+    )
+}
+
+// Resize the image to fit in the constraints, if any, and dither it if requested and useful
+fn prepare_image<'a>(
+    img: &'a DynamicImage,
+    config: &Config,
+    mode: BlockMode,
+) -> Cow<'a, DynamicImage> {
+    let mut img = Cow::Borrowed(img);
+
+    if config.resize {
+        img = Cow::Owned(super::resize_for_cells(&img, config, mode.cell_pixels()));
+    }
+
+    // reduce the colors before they are picked, so that the error can be spread around. The
+    // other modes average the pixels of a cell into two colors, which undoes the dithering.
+    if config.dither != Dither::None && mode == BlockMode::Half {
+        img = Cow::Owned(dither(&img, config.color_depth, config.dither));
+    }
+
+    img
+}
+
+/// Prints images with half blocks, remembering the cells of the last image it printed. Each
+/// image after the first one only updates the cells that changed, which avoids flicker and
+/// saves a lot of output when playing animations.
+///
+/// Every image has to be printed from the same cursor position, for example by setting
+/// [Config::restore_cursor](crate::Config::restore_cursor). [Config::block_mode](crate::Config::block_mode)
+/// is ignored, half blocks are always used.
+/// ## Example
+/// ```no_run
+/// use viuer::{BlockCanvas, Config};
+///
+/// let config = Config {
+///     restore_cursor: true,
+///     ..Default::default()
+/// };
+/// let mut canvas = BlockCanvas::new();
+/// let mut stdout = std::io::stdout();
+/// for name in &["frame1.png", "frame2.png"] {
+///     let img = image::open(name).expect("Could not open image.");
+///     canvas.draw(&mut stdout, &img, &config).expect("Drawing failed.");
+/// }
+/// ```
+#[derive(Default)]
+pub struct BlockCanvas {
+    rows: Vec<Vec<ColorSpec>>,
+}
+
+impl BlockCanvas {
+    /// Create a canvas which has not printed anything yet.
+    pub fn new() -> Self {
+        BlockCanvas::default()
+    }
+
+    /// Forget the last printed image, so that the next one is printed completely. Useful when
+    /// something else was printed over the image.
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    /// Print an image, only updating the cells that differ from the previous one. Returns the
+    /// size of the image in cells, like [print](crate::print).
+    pub fn draw(
+        &mut self,
+        stdout: &mut impl Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        if config.restore_cursor {
+            execute!(stdout, SavePosition)?;
+        }
+
+        let mut out_buffer = Buffer::ansi();
+
+        let img = fill_box(img, config);
+        let img = prepare_image(&img, config, BlockMode::Half);
+        let config = &align(config, cells(&img, BlockMode::Half.cell_pixels()));
+        adjust_y_offset(&mut out_buffer, config)?;
+        let (width, height) = img.dimensions();
+        let rows = half_block_rows(&img, config);
+
+        for (i, row) in rows.iter().enumerate() {
+            match self.rows.get(i) {
+                Some(previous) if previous.len() == row.len() => {
+                    diff_out_buffer(row, previous, &mut out_buffer, config.x)?
+                }
+                _ => {
+                    // move right if x offset is specified
+                    if config.x > 0 {
+                        execute!(out_buffer, MoveRight(config.x))?;
+                    }
+                    fill_out_buffer(row, &mut out_buffer)?;
+                }
             }
-        } else if config.y < 0 {
-            // MoveUp if negative
-            execute!(out_buffer, MoveToPreviousLine(-config.y as u16))?;
+            print_buffer(stdout, &mut out_buffer)?;
+        }
+        self.rows = rows;
+
+        if config.restore_cursor {
+            execute!(stdout, RestorePosition)?;
+        }
+
+        Ok((width, height / 2))
+    }
+}
+
+// Move the cursor to the row from where printing should start. The x offset is applied
+// separately on every line.
+pub(super) fn adjust_y_offset(out_buffer: &mut Buffer, config: &Config) -> ViuResult {
+    if config.absolute_offset {
+        if config.y >= 0 {
+            // If absolute_offset, move to (0,y).
+            execute!(out_buffer, MoveTo(0, config.y as u16))?;
         } else {
-            // Move down y lines
-            for _ in 0..config.y {
-                // writeln! is used instead of MoveDown to force scrolldown
-                // observed when config.y > 0 and cursor is on the last terminal line
-                writeln!(out_buffer)?;
+            //Negative values do not make sense.
+            return Err(ViuError::InvalidConfiguration(
+                "absolute_offset is true but y offset is negative".to_owned(),
+            ));
+        }
+    } else if config.y < 0 {
+        // MoveUp if negative
+        execute!(out_buffer, MoveToPreviousLine(-config.y as u16))?;
+    } else {
+        // Move down y lines
+        for _ in 0..config.y {
+            // writeln! is used instead of MoveDown to force scrolldown
+            // observed when config.y > 0 and cursor is on the last terminal line
+            writeln!(out_buffer)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_half_blocks(
+    stdout: &mut dyn Write,
+    out_buffer: &mut Buffer,
+    img: &DynamicImage,
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    // there are two types of buffers in this function:
+    // - out_buffer: Buffer, which is from termcolor crate. Used to buffer all writing
+    //   required to print a single image or frame. Flushed on every line
+    // - row: Vec<ColorSpec>, which stores back- and foreground colors for a
+    //   row of terminal cells. When filled in, its output goes into out_buffer.
+    // out_buffer is flushed on every terminal line (i.e 2 pixel rows)
+
+    let (width, height) = img.dimensions();
+
+    for row in half_block_rows(img, config) {
+        // move right if x offset is specified
+        if config.x > 0 {
+            execute!(out_buffer, MoveRight(config.x))?;
+        }
+
+        // fill out_buffer with the row
+        fill_out_buffer(&row, out_buffer)?;
+
+        // write the line to stdout
+        print_buffer(stdout, out_buffer)?;
+    }
+
+    // TODO: might be +1/2 ?
+    Ok((width, height / 2))
+}
+
+// Colors of every cell when the image is printed with half blocks, row by row. The background
+// of each ColorSpec is the top pixel and the foreground the bottom one, None being transparent.
+// If the image has an odd height, the last row only has top pixels.
+fn half_block_rows(img: &DynamicImage, config: &Config) -> Vec<Vec<ColorSpec>> {
+    let (width, height) = img.dimensions();
+    let mut rows = Vec::with_capacity(height.div_ceil(2) as usize);
+
+    for row_px in (0..height).step_by(2) {
+        let mut row = Vec::with_capacity(width as usize);
+        for col_px in 0..width {
+            let mut c = ColorSpec::new();
+            c.set_bg(get_half_block_color(img, row_px, col_px, config));
+            if row_px + 1 < height {
+                c.set_fg(get_half_block_color(img, row_px + 1, col_px, config));
             }
+            row.push(c);
         }
+        rows.push(row);
+    }
+
+    rows
+}
 
-        // resize the image so that it fits in the constraints, if any
-        let resized_img;
-        let img = if config.resize {
-            resized_img = super::resize(&img, config.width, config.height);
-            &resized_img
+// Color of a pixel in a half block. If the alpha of the pixel is 0, a predefined color based on
+// the position is used to mimic the checkerboard background. If the transparent option was given,
+// there is no color at all.
+fn get_half_block_color(img: &DynamicImage, row: u32, col: u32, config: &Config) -> Option<Color> {
+    let pixel = (col, row, img.get_pixel(col, row));
+    if is_pixel_transparent(pixel) {
+        if config.transparent {
+            None
         } else {
-            img
-        };
+            Some(get_transparency_color(row, col, config.color_depth))
+        }
+    } else {
+        Some(get_color_from_pixel(pixel, config.color_depth))
+    }
+}
+
+// Print the image with glyphs that display more than one pixel column per cell. Each cell can
+// only show two colors, so the best split of its pixels into foreground and background is used.
+fn print_cells(
+    stdout: &mut dyn Write,
+    out_buffer: &mut Buffer,
+    img: &DynamicImage,
+    config: &Config,
+    mode: BlockMode,
+) -> ViuResult<(u32, u32)> {
+    let (cell_width, cell_height) = mode.cell_pixels();
+
+    let (width, height) = img.dimensions();
+    let cols = width.div_ceil(cell_width);
+    let rows = height.div_ceil(cell_height);
+
+    let mut pixels = Vec::with_capacity((cell_width * cell_height) as usize);
+
+    for row in 0..rows {
+        // move right if x offset is specified
+        if config.x > 0 {
+            execute!(out_buffer, MoveRight(config.x))?;
+        }
 
-        let (width, _) = img.dimensions();
-
-        // TODO: position information is contained in the pixel
-        let mut curr_col_px = 0;
-        let mut curr_row_px = 0;
-
-        let mut row_buffer: Vec<ColorSpec> = Vec::with_capacity(width as usize);
-
-        // row_buffer building mode. At first the top colors are calculated and then the bottom
-        // Once the bottom row is ready, row_buffer is flushed
-        let mut mode = Mode::Top;
-
-        // iterate pixels and fill row_buffer
-        for pixel in img.pixels() {
-            // if the alpha of the pixel is 0, print a predefined pixel based on the position in order
-            // to mimic the checherboard background. If the transparent option was given, move right instead
-            let color = if is_pixel_transparent(pixel) {
-                if config.transparent {
-                    None
-                } else {
-                    Some(get_transparency_color(
-                        curr_row_px,
-                        curr_col_px,
-                        config.truecolor,
-                    ))
+        for col in 0..cols {
+            pixels.clear();
+            for y in row * cell_height..(row + 1) * cell_height {
+                for x in col * cell_width..(col + 1) * cell_width {
+                    pixels.push(get_cell_pixel(img, x, y, config.transparent));
                 }
-            } else {
-                Some(get_color_from_pixel(pixel, config.truecolor))
-            };
-
-            if mode == Mode::Top {
-                // add a new ColorSpec to row_buffer
-                let mut c = ColorSpec::new();
-                c.set_bg(color);
-                row_buffer.push(c);
-            } else {
-                // upgrade an already existing ColorSpec
-                let colorspec_to_upg = &mut row_buffer[curr_col_px as usize];
-                colorspec_to_upg.set_fg(color);
             }
 
-            curr_col_px += 1;
-            // if the buffer is full start adding the second row of pixels
-            if row_buffer.len() == width as usize {
-                if mode == Mode::Top {
-                    mode = Mode::Bottom;
-                    curr_col_px = 0;
-                    curr_row_px += 1;
+            match fit_cell(&pixels) {
+                Some(fit) => {
+                    let mut c = ColorSpec::new();
+                    c.set_fg(Some(get_color(fit.fg, config.color_depth)));
+                    c.set_bg(fit.bg.map(|bg| get_color(bg, config.color_depth)));
+                    out_buffer.set_color(&c)?;
+                    write!(out_buffer, "{}", mode.glyph(fit.mask))?;
                 }
-                // only if the second row is completed, flush the buffer and start again
-                else if curr_col_px == width {
-                    curr_col_px = 0;
-                    curr_row_px += 1;
+                // completely transparent
+                None => execute!(out_buffer, MoveRight(1))?,
+            }
+        }
 
-                    // move right if x offset is specified
-                    if config.x > 0 {
-                        execute!(out_buffer, MoveRight(config.x))?;
-                    }
+        out_buffer.reset()?;
+        writeln!(out_buffer)?;
+        print_buffer(stdout, out_buffer)?;
+    }
 
-                    // flush the row_buffer into out_buffer
-                    fill_out_buffer(&mut row_buffer, &mut out_buffer, false)?;
+    Ok((cols, rows))
+}
 
-                    // write the line to stdout
-                    print_buffer(&stdout, &mut out_buffer)?;
+// Color of the pixel at (x, y), or None if it should be left to the terminal's background
+fn get_cell_pixel(img: &DynamicImage, x: u32, y: u32, transparent: bool) -> Option<(u8, u8, u8)> {
+    // the last row or column of cells may be only partially covered by the image
+    if x >= img.width() || y >= img.height() {
+        return None;
+    }
 
-                    mode = Mode::Top;
-                } else {
-                    // in the middle of the second row, more iterations are required
-                }
-            }
+    let pixel = img.get_pixel(x, y);
+    if is_pixel_transparent((x, y, pixel)) {
+        if transparent {
+            None
+        } else {
+            Some(get_transparency_rgb(y, x))
         }
+    } else {
+        Some((pixel[0], pixel[1], pixel[2]))
+    }
+}
+
+// The colors to print a single cell with
+struct CellFit {
+    // pixels which are drawn with the foreground color
+    mask: u8,
+    fg: (u8, u8, u8),
+    // None if the terminal's background should be visible
+    bg: Option<(u8, u8, u8)>,
+}
 
-        // buffer will be flushed if the image has an odd height
-        if !row_buffer.is_empty() {
-            fill_out_buffer(&mut row_buffer, &mut out_buffer, true)?;
+// Split the pixels of a cell into a foreground and a background group, so that the pixels are
+// as close as possible to the average color of their group. Returns None if all the pixels are
+// transparent.
+fn fit_cell(pixels: &[Option<(u8, u8, u8)>]) -> Option<CellFit> {
+    let opaque = pixels
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_some())
+        .fold(0u8, |mask, (i, _)| mask | 1 << i);
+    let all = (1u16 << pixels.len()) - 1;
+
+    if opaque == 0 {
+        return None;
+    }
+    // transparent pixels have to be in the background, leaving a single color for the rest
+    if u16::from(opaque) != all {
+        return Some(CellFit {
+            mask: opaque,
+            fg: average_color(pixels, opaque),
+            bg: None,
+        });
+    }
+
+    // Minimizing the squared distance to the group averages is the same as maximizing the sum
+    // of |sum of group|^2 / size of group. The last pixel is always in the background, so that
+    // each split is checked only once.
+    let score = |mask: u8| {
+        let (mut fg, mut bg) = (([0f64; 3], 0f64), ([0f64; 3], 0f64));
+        for (i, p) in pixels.iter().enumerate() {
+            let (r, g, b) = p.unwrap_or_default();
+            let group = if mask & 1 << i != 0 { &mut fg } else { &mut bg };
+            group.0[0] += f64::from(r);
+            group.0[1] += f64::from(g);
+            group.0[2] += f64::from(b);
+            group.1 += 1.0;
         }
+        [fg, bg]
+            .iter()
+            .filter(|(_, n)| *n > 0.0)
+            .map(|(sum, n)| sum.iter().map(|c| c * c).sum::<f64>() / n)
+            .sum::<f64>()
+    };
 
-        // do a final write to stdout to print last row if length is odd, and reset cursor position
-        print_buffer(&stdout, &mut out_buffer)?;
+    // on ties, prefer the split that comes first, so that uniform cells use a single color
+    let mut best = (0, score(0));
+    for mask in 1..1u8 << (pixels.len() - 1) {
+        let s = score(mask);
+        if s > best.1 {
+            best = (mask, s);
+        }
+    }
+    let best = best.0;
+
+    if best == 0 {
+        // all pixels are in a single group
+        Some(CellFit {
+            mask: opaque,
+            fg: average_color(pixels, opaque),
+            bg: None,
+        })
+    } else {
+        Some(CellFit {
+            mask: best,
+            fg: average_color(pixels, best),
+            bg: Some(average_color(pixels, opaque & !best)),
+        })
+    }
+}
 
-        // TODO: might be +1/2 ?
-        Ok((width, curr_row_px / 2))
+// Average color of the pixels set in the mask
+fn average_color(pixels: &[Option<(u8, u8, u8)>], mask: u8) -> (u8, u8, u8) {
+    let (mut sum, mut n) = ([0u32; 3], 0u32);
+    for (i, p) in pixels.iter().enumerate() {
+        if let Some((r, g, b)) = p {
+            if mask & 1 << i != 0 {
+                sum[0] += u32::from(*r);
+                sum[1] += u32::from(*g);
+                sum[2] += u32::from(*b);
+                n += 1;
+            }
+        }
     }
+    let n = std::cmp::max(n, 1);
+    ((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8)
 }
 
 // Send out_buffer to stdout. Empties it when it's done
-fn print_buffer(stdout: &BufferWriter, out_buffer: &mut Buffer) -> ViuResult {
-    match stdout.print(out_buffer) {
+pub(super) fn print_buffer(stdout: &mut dyn Write, out_buffer: &mut Buffer) -> ViuResult {
+    match stdout
+        .write_all(out_buffer.as_slice())
+        .and_then(|_| stdout.flush())
+    {
         Ok(_) => {
             out_buffer.clear();
             Ok(())
@@ -163,102 +532,104 @@ fn print_buffer(stdout: &BufferWriter, out_buffer: &mut Buffer) -> ViuResult {
 }
 
 // Translates the row_buffer, containing colors, into the out_buffer which will be flushed to the terminal
-fn fill_out_buffer(
-    row_buffer: &mut Vec<ColorSpec>,
+fn fill_out_buffer(row: &[ColorSpec], out_buffer: &mut Buffer) -> ViuResult {
+    for c in row {
+        write_half_block(c, out_buffer)?;
+    }
+
+    out_buffer.reset()?;
+    writeln!(out_buffer)?;
+
+    Ok(())
+}
+
+// Write only the cells of the row which are different from the previous one, jumping over the
+// others. Transparent cells which changed are cleared, as nothing would be drawn over them.
+fn diff_out_buffer(
+    row: &[ColorSpec],
+    previous: &[ColorSpec],
     out_buffer: &mut Buffer,
-    is_last_row: bool,
+    x_offset: u16,
 ) -> ViuResult {
-    let mut out_color;
-    let mut out_char;
-    let mut new_color;
-
-    for c in row_buffer.iter() {
-        // If a flush is needed it means that only one row with UPPER_HALF_BLOCK must be printed
-        // because it is the last row, hence it contains only 1 pixel
-        if is_last_row {
-            new_color = ColorSpec::new();
-            if let Some(bg) = c.bg() {
-                new_color.set_fg(Some(*bg));
-                out_char = UPPER_HALF_BLOCK;
-            } else {
-                execute!(out_buffer, MoveRight(1))?;
-                continue;
-            }
-            out_color = &new_color;
+    // the line is written from its first column
+    let mut cursor = 0;
+
+    for (col, (c, prev)) in row.iter().zip(previous).enumerate() {
+        if c == prev {
+            continue;
+        }
+
+        let target = x_offset + col as u16;
+        if target > cursor {
+            execute!(out_buffer, MoveRight(target - cursor))?;
+        }
+
+        if c.fg().is_none() && c.bg().is_none() {
+            out_buffer.reset()?;
+            write!(out_buffer, " ")?;
         } else {
-            match (c.fg(), c.bg()) {
-                (None, None) => {
-                    // completely transparent
-                    execute!(out_buffer, MoveRight(1))?;
-                    continue;
-                }
-                (Some(bottom), None) => {
-                    // only top transparent
-                    new_color = ColorSpec::new();
-                    new_color.set_fg(Some(*bottom));
-                    out_color = &new_color;
-                    out_char = LOWER_HALF_BLOCK;
-                }
-                (None, Some(top)) => {
-                    // only bottom transparent
-                    new_color = ColorSpec::new();
-                    new_color.set_fg(Some(*top));
-                    out_color = &new_color;
-                    out_char = UPPER_HALF_BLOCK;
-                }
-                (Some(_top), Some(_bottom)) => {
-                    // both parts have a color
-                    out_color = c;
-                    out_char = LOWER_HALF_BLOCK;
-                }
-            }
+            write_half_block(c, out_buffer)?;
         }
-        out_buffer.set_color(out_color)?;
-        write!(out_buffer, "{}", out_char)?;
+        cursor = target + 1;
     }
 
     out_buffer.reset()?;
     writeln!(out_buffer)?;
-    row_buffer.clear();
 
     Ok(())
 }
 
+// Write a single cell, where the background is the top pixel and the foreground the bottom one
+fn write_half_block(c: &ColorSpec, out_buffer: &mut Buffer) -> ViuResult {
+    let mut new_color = ColorSpec::new();
+    let (out_color, out_char) = match (c.fg(), c.bg()) {
+        (None, None) => {
+            // completely transparent
+            execute!(out_buffer, MoveRight(1))?;
+            return Ok(());
+        }
+        (Some(bottom), None) => {
+            // only top transparent
+            new_color.set_fg(Some(*bottom));
+            (&new_color, LOWER_HALF_BLOCK)
+        }
+        (None, Some(top)) => {
+            // only bottom transparent, or the last row of an image with an odd height
+            new_color.set_fg(Some(*top));
+            (&new_color, UPPER_HALF_BLOCK)
+        }
+        (Some(_top), Some(_bottom)) => {
+            // both parts have a color
+            (c, LOWER_HALF_BLOCK)
+        }
+    };
+
+    out_buffer.set_color(out_color)?;
+    write!(out_buffer, "{}", out_char)?;
+    Ok(())
+}
+
 fn is_pixel_transparent(pixel: (u32, u32, Rgba<u8>)) -> bool {
     let (_x, _y, data) = pixel;
     data[3] == 0
 }
 
-fn get_transparency_color(row: u32, col: u32, truecolor: bool) -> Color {
+fn get_transparency_color(row: u32, col: u32, depth: ColorDepth) -> Color {
+    get_color(get_transparency_rgb(row, col), depth)
+}
+
+pub(super) fn get_transparency_rgb(row: u32, col: u32) -> (u8, u8, u8) {
     //imitate the transparent chess board pattern
-    let rgb = if row % 2 == col % 2 {
+    if row % 2 == col % 2 {
         CHECKERBOARD_BACKGROUND_DARK
     } else {
         CHECKERBOARD_BACKGROUND_LIGHT
-    };
-    if truecolor {
-        Color::Rgb(rgb.0, rgb.1, rgb.2)
-    } else {
-        Color::Ansi256(ansi256_from_rgb(rgb))
     }
 }
 
-fn get_color_from_pixel(pixel: (u32, u32, Rgba<u8>), truecolor: bool) -> Color {
+fn get_color_from_pixel(pixel: (u32, u32, Rgba<u8>), depth: ColorDepth) -> Color {
     let (_x, _y, data) = pixel;
-    let rgb = (data[0], data[1], data[2]);
-    if truecolor {
-        Color::Rgb(rgb.0, rgb.1, rgb.2)
-    } else {
-        Color::Ansi256(ansi256_from_rgb(rgb))
-    }
-}
-
-// enum used to keep track where the current line of pixels processed should be displayed - as
-// background or foreground color
-#[derive(PartialEq)]
-enum Mode {
-    Top,
-    Bottom,
+    get_color((data[0], data[1], data[2]), depth)
 }
 
 #[cfg(test)]
@@ -276,7 +647,9 @@ mod tests {
             transparent: true,
             ..Default::default()
         };
-        let (w, h) = BlockPrinter {}.print(&img, &config).unwrap();
+        let (w, h) = BlockPrinter {}
+            .print(&mut std::io::stdout(), &img, &config)
+            .unwrap();
 
         assert_eq!(w, 20);
         assert_eq!(h, 3);
@@ -294,9 +667,143 @@ mod tests {
             transparent: true,
             ..Default::default()
         };
-        let (w, h) = BlockPrinter {}.print(&img, &config).unwrap();
+        let (w, h) = BlockPrinter {}
+            .print(&mut std::io::stdout(), &img, &config)
+            .unwrap();
 
         assert_eq!(w, 160);
         assert_eq!(h, 40);
     }
+
+    #[test]
+    fn test_block_printer_to_buffer() {
+        let img =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])));
+
+        let config = Config {
+            absolute_offset: false,
+            color_depth: ColorDepth::Truecolor,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        let (w, h) = BlockPrinter {}.print(&mut buf, &img, &config).unwrap();
+
+        assert_eq!(w, 2);
+        assert_eq!(h, 1);
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert_eq!(out.matches(LOWER_HALF_BLOCK).count(), 2);
+        assert!(out.contains("\x1b[38;2;255;0;0m"));
+    }
+
+    #[test]
+    fn test_block_canvas() {
+        let mut img = image::RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let config = Config {
+            absolute_offset: false,
+            color_depth: ColorDepth::Truecolor,
+            ..Default::default()
+        };
+        let mut canvas = BlockCanvas::new();
+
+        let mut buf: Vec<u8> = Vec::new();
+        let img_1 = DynamicImage::ImageRgba8(img.clone());
+        assert_eq!(canvas.draw(&mut buf, &img_1, &config).unwrap(), (2, 1));
+        assert_eq!(
+            std::str::from_utf8(&buf)
+                .unwrap()
+                .matches(LOWER_HALF_BLOCK)
+                .count(),
+            2
+        );
+
+        // only the changed cell is printed
+        img.put_pixel(1, 1, Rgba([0, 0, 255, 255]));
+        let img_2 = DynamicImage::ImageRgba8(img);
+        let mut buf: Vec<u8> = Vec::new();
+        canvas.draw(&mut buf, &img_2, &config).unwrap();
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert!(out.starts_with("\x1b[1C"));
+        assert!(out.contains("\x1b[38;2;0;0;255m\x1b[48;2;255;0;0m\u{2584}"));
+        assert_eq!(out.matches(LOWER_HALF_BLOCK).count(), 1);
+
+        // nothing is printed for the same image
+        let mut buf: Vec<u8> = Vec::new();
+        canvas.draw(&mut buf, &img_2, &config).unwrap();
+        assert_eq!(std::str::from_utf8(&buf).unwrap(), "\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_block_printer_quarter() {
+        let mut img = image::RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
+        img.put_pixel(1, 1, Rgba([0, 0, 255, 255]));
+        let img = DynamicImage::ImageRgba8(img);
+
+        let config = Config {
+            absolute_offset: false,
+            resize: false,
+            color_depth: ColorDepth::Truecolor,
+            block_mode: BlockMode::Quarter,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        let (w, h) = BlockPrinter {}.print(&mut buf, &img, &config).unwrap();
+
+        assert_eq!(w, 1);
+        assert_eq!(h, 1);
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert!(out.contains("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{258C}"));
+    }
+
+    #[test]
+    fn test_fit_cell() {
+        let red = Some((255, 0, 0));
+        let blue = Some((0, 0, 255));
+
+        let fit = fit_cell(&[red, blue, blue, blue]).unwrap();
+        assert_eq!(fit.mask, 0b0001);
+        assert_eq!(fit.fg, (255, 0, 0));
+        assert_eq!(fit.bg, Some((0, 0, 255)));
+
+        let fit = fit_cell(&[red, red, red, red]).unwrap();
+        assert_eq!(fit.mask, 0b1111);
+        assert_eq!(fit.bg, None);
+
+        let fit = fit_cell(&[None, red, None, blue]).unwrap();
+        assert_eq!(fit.mask, 0b1010);
+        assert_eq!(fit.fg, (127, 0, 127));
+        assert_eq!(fit.bg, None);
+
+        assert!(fit_cell(&[None, None, None, None]).is_none());
+    }
+
+    #[test]
+    fn test_legacy_glyphs() {
+        assert_eq!(BlockMode::Sextant.glyph(0b00_0001), '\u{1FB00}');
+        assert_eq!(BlockMode::Sextant.glyph(0b01_0101), '\u{258C}');
+        assert_eq!(BlockMode::Sextant.glyph(0b01_0110), '\u{1FB14}');
+        assert_eq!(BlockMode::Sextant.glyph(0b11_1110), '\u{1FB3B}');
+
+        assert_eq!(BlockMode::Octant.glyph(0x04), '\u{1CD00}');
+        assert_eq!(BlockMode::Octant.glyph(0x06), '\u{1CD01}');
+        assert_eq!(BlockMode::Octant.glyph(0x10), '\u{1CD09}');
+        assert_eq!(BlockMode::Octant.glyph(0xF0), '\u{2584}');
+        assert_eq!(BlockMode::Octant.glyph(0xFE), '\u{1CDE5}');
+    }
+
+    #[test]
+    fn test_block_printer_octant() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(40, 25));
+
+        let config = Config {
+            absolute_offset: false,
+            block_mode: BlockMode::Octant,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        let (w, h) = BlockPrinter {}.print(&mut buf, &img, &config).unwrap();
+
+        assert_eq!(w, 20);
+        assert_eq!(h, 6);
+    }
 }
@@ -0,0 +1,200 @@
+use crate::color::get_color;
+use crate::error::ViuResult;
+use crate::printer::block::{adjust_y_offset, cells, print_buffer};
+use crate::printer::{align, Printer};
+use crate::Config;
+
+use image::{DynamicImage, GenericImageView};
+use std::io::Write;
+use termcolor::{Buffer, ColorSpec, WriteColor};
+
+use crossterm::cursor::MoveRight;
+use crossterm::execute;
+
+const BRAILLE_BLANK: u32 = 0x2800;
+
+// Bit of each dot in a braille pattern, indexed by [row][column] in the 2x4 cell
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub struct BraillePrinter {}
+
+impl Printer for BraillePrinter {
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        let mut out_buffer = Buffer::ansi();
+
+        // resize the image so that it fits in the constraints, if any
+        let resized_img;
+        let img = if config.resize {
+            resized_img = super::resize_for_cells(img, config, (2, 4));
+            &resized_img
+        } else {
+            img
+        };
+
+        let config = &align(config, cells(img, (2, 4)));
+        adjust_y_offset(&mut out_buffer, config)?;
+
+        let (width, height) = img.dimensions();
+        let dots = get_dots(img, config.braille_threshold, config.braille_dither);
+
+        let cols = width.div_ceil(2);
+        let rows = height.div_ceil(4);
+
+        for row in 0..rows {
+            // move right if x offset is specified
+            if config.x > 0 {
+                execute!(out_buffer, MoveRight(config.x))?;
+            }
+
+            for col in 0..cols {
+                let mut pattern = 0;
+                let mut sum = [0u32; 3];
+                let mut n = 0;
+
+                for (dy, bits) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dx, bit) in bits.iter().enumerate() {
+                        let (x, y) = (2 * col + dx as u32, 4 * row + dy as u32);
+                        if x < width && y < height && dots[(y * width + x) as usize] {
+                            let pixel = img.get_pixel(x, y);
+                            sum[0] += u32::from(pixel[0]);
+                            sum[1] += u32::from(pixel[1]);
+                            sum[2] += u32::from(pixel[2]);
+                            n += 1;
+                            pattern |= bit;
+                        }
+                    }
+                }
+
+                if pattern != 0 {
+                    let rgb = ((sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8);
+                    let mut c = ColorSpec::new();
+                    c.set_fg(Some(get_color(rgb, config.color_depth)));
+                    out_buffer.set_color(&c)?;
+                } else {
+                    out_buffer.reset()?;
+                }
+                // all values between U+2800 and U+28FF are valid braille patterns
+                let glyph = std::char::from_u32(BRAILLE_BLANK + pattern).unwrap_or(' ');
+                write!(out_buffer, "{}", glyph)?;
+            }
+
+            out_buffer.reset()?;
+            writeln!(out_buffer)?;
+            print_buffer(stdout, &mut out_buffer)?;
+        }
+
+        Ok((cols, rows))
+    }
+}
+
+// Decide which pixels of the image are drawn as dots. A pixel is drawn if its luminance is above
+// the threshold. Transparent pixels are never drawn.
+fn get_dots(img: &DynamicImage, threshold: u8, dither: bool) -> Vec<bool> {
+    let (width, height) = img.dimensions();
+    let mut luma: Vec<f32> = img
+        .pixels()
+        .map(|(_, _, p)| {
+            if p[3] == 0 {
+                0.0
+            } else {
+                0.299 * f32::from(p[0]) + 0.587 * f32::from(p[1]) + 0.114 * f32::from(p[2])
+            }
+        })
+        .collect();
+    let transparent: Vec<bool> = img.pixels().map(|(_, _, p)| p[3] == 0).collect();
+
+    let threshold = f32::from(threshold);
+    let mut dots = Vec::with_capacity(luma.len());
+
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let i = y * width as usize + x;
+            let on = !transparent[i] && luma[i] > threshold;
+            dots.push(on);
+
+            if dither {
+                // Floyd-Steinberg: spread the error to the neighbours that are not processed yet
+                let err = luma[i] - if on { 255.0 } else { 0.0 };
+                let mut spread = |dx: isize, dy: usize, weight: f32| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < width as usize && y + dy < height as usize {
+                        luma[(y + dy) * width as usize + nx as usize] += err * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+    }
+
+    dots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorDepth;
+    use image::Rgba;
+
+    #[test]
+    fn test_braille_printer() {
+        let mut img = image::RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        // leave only the top left dot in the second cell
+        for y in 0..4 {
+            for x in 2..4 {
+                if (x, y) != (2, 0) {
+                    img.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+                }
+            }
+        }
+        let img = DynamicImage::ImageRgba8(img);
+
+        let config = Config {
+            absolute_offset: false,
+            resize: false,
+            color_depth: ColorDepth::Truecolor,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        let (w, h) = BraillePrinter {}.print(&mut buf, &img, &config).unwrap();
+
+        assert_eq!(w, 2);
+        assert_eq!(h, 1);
+        let out = std::str::from_utf8(&buf).unwrap();
+        assert!(out.contains("\x1b[38;2;255;255;255m\u{28FF}"));
+        assert!(out.contains("\x1b[38;2;255;255;255m\u{2801}"));
+    }
+
+    #[test]
+    fn test_get_dots_threshold() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            2,
+            4,
+            Rgba([100, 100, 100, 255]),
+        ));
+        assert!(get_dots(&img, 90, false).iter().all(|d| *d));
+        assert!(get_dots(&img, 110, false).iter().all(|d| !d));
+
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(2, 4));
+        assert!(get_dots(&img, 0, false).iter().all(|d| !d));
+    }
+
+    #[test]
+    fn test_get_dots_dither() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            8,
+            8,
+            Rgba([128, 128, 128, 255]),
+        ));
+        let on = get_dots(&img, 128, true).iter().filter(|d| **d).count();
+        // roughly half of the pixels should be drawn
+        assert!(on > 16 && on < 48);
+    }
+}
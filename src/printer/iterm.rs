@@ -1,5 +1,6 @@
 use crate::error::ViuResult;
-use crate::printer::{adjust_offset, find_best_fit, Printer};
+use crate::printer::{adjust_offset, align, find_best_fit, FitMode, Printer};
+use crate::utils::write_passthrough;
 use crate::Config;
 use image::{DynamicImage, GenericImageView};
 use lazy_static::lazy_static;
@@ -18,22 +19,32 @@ pub fn is_iterm_supported() -> bool {
 }
 
 impl Printer for iTermPrinter {
-    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
         let (width, height) = img.dimensions();
 
         // Transform the dynamic image to a PNG which can be given directly to iTerm
         let mut png_bytes: Vec<u8> = Vec::new();
-        let _ = image::codecs::png::PngEncoder::new(&mut png_bytes).encode(
+        image::codecs::png::PngEncoder::new(&mut png_bytes).encode(
             img.as_bytes(),
             width,
             height,
             img.color(),
         )?;
 
-        print_buffer(img, &png_bytes[..], config)
+        print_buffer(stdout, img, &png_bytes[..], config)
     }
 
-    fn print_from_file(&self, filename: &str, config: &Config) -> ViuResult<(u32, u32)> {
+    fn print_from_file(
+        &self,
+        stdout: &mut dyn Write,
+        filename: &str,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
         let file = std::fs::File::open(filename)?;
 
         // load the file content
@@ -42,46 +53,45 @@ impl Printer for iTermPrinter {
         buf_reader.read_to_end(&mut file_content)?;
 
         let img = image::load_from_memory(&file_content[..])?;
-        print_buffer(&img, &file_content[..], config)
+        print_buffer(stdout, &img, &file_content[..], config)
     }
 }
 
 // This function requires both a DynamicImage, which is used to calculate dimensions,
 // and it's raw representation as a file, because that's the data iTerm needs to display it.
-fn print_buffer(img: &DynamicImage, img_content: &[u8], config: &Config) -> ViuResult<(u32, u32)> {
-    let mut stdout = std::io::stdout();
-
-    adjust_offset(&mut stdout, config)?;
-
-    // Note: find_best_fit is not necessary for iTerm2. It will already fit to the terminal size if
-    // no height or width are provided. If only one is provided, it will scale the aspect ratio
-    // appropriately. Additionally, it's calculations don't seem to be working properly with iTerm2
-    // anyway.
-    // TODO: Keeping find_best_fit here anyway just because we need a ViuResult.
-    // TODO: Maybe fix find_best_fit instead? It would be more elegant.
-    let (w, h) = find_best_fit(&img, config.width, config.height);
-
-    let w_str = match config.width {
-        Some(w) => format!("width={};", w),
-        None => "".to_string(),
+fn print_buffer(
+    stdout: &mut dyn Write,
+    img: &DynamicImage,
+    img_content: &[u8],
+    config: &Config,
+) -> ViuResult<(u32, u32)> {
+    let placement = find_best_fit(img, config);
+
+    adjust_offset(stdout, &align(config, (placement.columns, placement.rows)))?;
+
+    // iTerm is given the size that was picked for the fit mode, so that it draws what the offset
+    // and the returned size were computed for. Only stretched images may change their shape.
+    let preserve_aspect_ratio = if config.fit_mode == FitMode::Stretch {
+        0
+    } else {
+        1
     };
 
-    let h_str = match config.height {
-        Some(h) => format!("height={};", h),
-        None => "".to_string(),
-    };
-
-    writeln!(
+    write_passthrough(
         stdout,
-        "\x1b]1337;File=inline=1;preserveAspectRatio=1;size={};{}{}:{}\x07",
-        img_content.len(),
-        w_str,
-        h_str,
-        base64::encode(img_content)
+        &format!(
+            "\x1b]1337;File=inline=1;preserveAspectRatio={};size={};width={};height={}:{}\x07",
+            preserve_aspect_ratio,
+            img_content.len(),
+            placement.columns,
+            placement.rows,
+            base64::encode(img_content)
+        ),
     )?;
+    writeln!(stdout)?;
     stdout.flush()?;
 
-    Ok((w, h))
+    Ok((placement.columns, placement.rows))
 }
 
 // Check if the iTerm protocol can be used
@@ -91,5 +101,46 @@ fn check_iterm_support() -> bool {
             return true;
         }
     }
-    false
+    // tmux replaces TERM_PROGRAM with its own name, but iTerm also sets LC_TERMINAL
+    std::env::var("LC_TERMINAL").is_ok_and(|term| term == "iTerm2")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_buffer_fit_modes() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 10));
+        let print = |fit_mode| {
+            let config = Config {
+                width: Some(40),
+                height: Some(40),
+                absolute_offset: false,
+                fit_mode,
+                ..Default::default()
+            };
+            let mut vec = Vec::new();
+            let size = print_buffer(&mut vec, &img, b"png", &config).unwrap();
+            (String::from_utf8(vec).unwrap(), size)
+        };
+
+        let cases = [
+            (FitMode::Stretch, 0, (40, 40)),
+            (FitMode::Contain, 1, (40, 10)),
+            (FitMode::ScaleDown, 1, (20, 5)),
+            (FitMode::Integer, 1, (40, 10)),
+            (FitMode::Cover, 1, (40, 40)),
+            (FitMode::Fill, 1, (40, 40)),
+        ];
+        for &(fit_mode, preserve, (w, h)) in cases.iter() {
+            let (out, size) = print(fit_mode);
+            let expected = format!(
+                "\x1b]1337;File=inline=1;preserveAspectRatio={};size=3;width={};height={}:cG5n\x07\n",
+                preserve, w, h
+            );
+            assert_eq!(out, expected, "{:?}", fit_mode);
+            assert_eq!(size, (w, h), "{:?}", fit_mode);
+        }
+    }
 }
@@ -1,12 +1,23 @@
 use crate::error::{ViuError, ViuResult};
-use crate::printer::{adjust_offset, find_best_fit, Printer};
+use crate::printer::{
+    adjust_offset, align, fill_box, filter_type, find_best_fit, find_best_fit_for_size, FitMode,
+    Printer, SizeUnit,
+};
+use crate::utils::{self, write_passthrough, Multiplexer};
 use crate::Config;
 use console::{Key, Term};
-use image::GenericImageView;
+use crossterm::cursor::{MoveRight, MoveTo, RestorePosition, SavePosition};
+use crossterm::{execute, ExecutableCommand};
+use image::{DynamicImage, Frame, GenericImageView, ImageFormat};
 use lazy_static::lazy_static;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+#[cfg(unix)]
+use std::ffi::CString;
 use std::io::Write;
-use std::io::{Error, ErrorKind};
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::atomic::{AtomicU32, Ordering};
 
+/// Printer using the Kitty graphics protocol.
 pub struct KittyPrinter {}
 
 lazy_static! {
@@ -19,23 +30,789 @@ pub fn get_kitty_support() -> KittySupport {
 }
 
 impl Printer for KittyPrinter {
-    fn print(&self, img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-        match get_kitty_support() {
-            KittySupport::None => Err(ViuError::KittyNotSupported),
-            KittySupport::Local => {
-                // print from file
-                print_local(img, config)
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &image::DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        let (payload, size) = get_sized_payload(img, config);
+        print_payload(stdout, payload, size, config, None)
+    }
+
+    fn print_from_file(
+        &self,
+        stdout: &mut dyn Write,
+        filename: &str,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        let file_content = std::fs::read(filename)?;
+
+        // Kitty can decode PNGs itself, so they are sent as they are. That is much smaller than
+        // the decoded pixels. Images sized in pixels have to be resized first.
+        let is_png = matches!(image::guess_format(&file_content), Ok(ImageFormat::Png));
+        if is_png && config.size_unit != SizeUnit::Pixels {
+            let dimensions = image::io::Reader::new(Cursor::new(&file_content))
+                .with_guessed_format()?
+                .into_dimensions()?;
+            let placement = find_best_fit_for_size(dimensions, config, (1, 2));
+            let size = (placement.columns, placement.rows);
+            let payload = Payload {
+                data: file_content,
+                keys: "f=100".to_owned(),
+            };
+            return print_payload(stdout, payload, size, config, None);
+        }
+
+        let img = image::load_from_memory(&file_content)?;
+        self.print(stdout, &img, config)
+    }
+}
+
+// Character whose cells are replaced by an image with a virtual placement
+const PLACEHOLDER: char = '\u{10EEEE}';
+
+// Diacritics encoding the row and column of a placeholder cell, and the upper bits of the image
+// id. Taken from Kitty's rowcolumn-diacritics.txt, the index of a diacritic is the value it encodes.
+const PLACEHOLDER_DIACRITICS: [char; 297] = [
+    '\u{0305}',
+    '\u{030D}',
+    '\u{030E}',
+    '\u{0310}',
+    '\u{0312}',
+    '\u{033D}',
+    '\u{033E}',
+    '\u{033F}',
+    '\u{0346}',
+    '\u{034A}',
+    '\u{034B}',
+    '\u{034C}',
+    '\u{0350}',
+    '\u{0351}',
+    '\u{0352}',
+    '\u{0357}',
+    '\u{035B}',
+    '\u{0363}',
+    '\u{0364}',
+    '\u{0365}',
+    '\u{0366}',
+    '\u{0367}',
+    '\u{0368}',
+    '\u{0369}',
+    '\u{036A}',
+    '\u{036B}',
+    '\u{036C}',
+    '\u{036D}',
+    '\u{036E}',
+    '\u{036F}',
+    '\u{0483}',
+    '\u{0484}',
+    '\u{0485}',
+    '\u{0486}',
+    '\u{0487}',
+    '\u{0592}',
+    '\u{0593}',
+    '\u{0594}',
+    '\u{0595}',
+    '\u{0597}',
+    '\u{0598}',
+    '\u{0599}',
+    '\u{059C}',
+    '\u{059D}',
+    '\u{059E}',
+    '\u{059F}',
+    '\u{05A0}',
+    '\u{05A1}',
+    '\u{05A8}',
+    '\u{05A9}',
+    '\u{05AB}',
+    '\u{05AC}',
+    '\u{05AF}',
+    '\u{05C4}',
+    '\u{0610}',
+    '\u{0611}',
+    '\u{0612}',
+    '\u{0613}',
+    '\u{0614}',
+    '\u{0615}',
+    '\u{0616}',
+    '\u{0617}',
+    '\u{0657}',
+    '\u{0658}',
+    '\u{0659}',
+    '\u{065A}',
+    '\u{065B}',
+    '\u{065D}',
+    '\u{065E}',
+    '\u{06D6}',
+    '\u{06D7}',
+    '\u{06D8}',
+    '\u{06D9}',
+    '\u{06DA}',
+    '\u{06DB}',
+    '\u{06DC}',
+    '\u{06DF}',
+    '\u{06E0}',
+    '\u{06E1}',
+    '\u{06E2}',
+    '\u{06E4}',
+    '\u{06E7}',
+    '\u{06E8}',
+    '\u{06EB}',
+    '\u{06EC}',
+    '\u{0730}',
+    '\u{0732}',
+    '\u{0733}',
+    '\u{0735}',
+    '\u{0736}',
+    '\u{073A}',
+    '\u{073D}',
+    '\u{073F}',
+    '\u{0740}',
+    '\u{0741}',
+    '\u{0743}',
+    '\u{0745}',
+    '\u{0747}',
+    '\u{0749}',
+    '\u{074A}',
+    '\u{07EB}',
+    '\u{07EC}',
+    '\u{07ED}',
+    '\u{07EE}',
+    '\u{07EF}',
+    '\u{07F0}',
+    '\u{07F1}',
+    '\u{07F3}',
+    '\u{0816}',
+    '\u{0817}',
+    '\u{0818}',
+    '\u{0819}',
+    '\u{081B}',
+    '\u{081C}',
+    '\u{081D}',
+    '\u{081E}',
+    '\u{081F}',
+    '\u{0820}',
+    '\u{0821}',
+    '\u{0822}',
+    '\u{0823}',
+    '\u{0825}',
+    '\u{0826}',
+    '\u{0827}',
+    '\u{0829}',
+    '\u{082A}',
+    '\u{082B}',
+    '\u{082C}',
+    '\u{082D}',
+    '\u{0951}',
+    '\u{0953}',
+    '\u{0954}',
+    '\u{0F82}',
+    '\u{0F83}',
+    '\u{0F86}',
+    '\u{0F87}',
+    '\u{135D}',
+    '\u{135E}',
+    '\u{135F}',
+    '\u{17DD}',
+    '\u{193A}',
+    '\u{1A17}',
+    '\u{1A75}',
+    '\u{1A76}',
+    '\u{1A77}',
+    '\u{1A78}',
+    '\u{1A79}',
+    '\u{1A7A}',
+    '\u{1A7B}',
+    '\u{1A7C}',
+    '\u{1B6B}',
+    '\u{1B6D}',
+    '\u{1B6E}',
+    '\u{1B6F}',
+    '\u{1B70}',
+    '\u{1B71}',
+    '\u{1B72}',
+    '\u{1B73}',
+    '\u{1CD0}',
+    '\u{1CD1}',
+    '\u{1CD2}',
+    '\u{1CDA}',
+    '\u{1CDB}',
+    '\u{1CE0}',
+    '\u{1DC0}',
+    '\u{1DC1}',
+    '\u{1DC3}',
+    '\u{1DC4}',
+    '\u{1DC5}',
+    '\u{1DC6}',
+    '\u{1DC7}',
+    '\u{1DC8}',
+    '\u{1DC9}',
+    '\u{1DCB}',
+    '\u{1DCC}',
+    '\u{1DD1}',
+    '\u{1DD2}',
+    '\u{1DD3}',
+    '\u{1DD4}',
+    '\u{1DD5}',
+    '\u{1DD6}',
+    '\u{1DD7}',
+    '\u{1DD8}',
+    '\u{1DD9}',
+    '\u{1DDA}',
+    '\u{1DDB}',
+    '\u{1DDC}',
+    '\u{1DDD}',
+    '\u{1DDE}',
+    '\u{1DDF}',
+    '\u{1DE0}',
+    '\u{1DE1}',
+    '\u{1DE2}',
+    '\u{1DE3}',
+    '\u{1DE4}',
+    '\u{1DE5}',
+    '\u{1DE6}',
+    '\u{1DFE}',
+    '\u{20D0}',
+    '\u{20D1}',
+    '\u{20D4}',
+    '\u{20D5}',
+    '\u{20D6}',
+    '\u{20D7}',
+    '\u{20DB}',
+    '\u{20DC}',
+    '\u{20E1}',
+    '\u{20E7}',
+    '\u{20E9}',
+    '\u{20F0}',
+    '\u{2CEF}',
+    '\u{2CF0}',
+    '\u{2CF1}',
+    '\u{2DE0}',
+    '\u{2DE1}',
+    '\u{2DE2}',
+    '\u{2DE3}',
+    '\u{2DE4}',
+    '\u{2DE5}',
+    '\u{2DE6}',
+    '\u{2DE7}',
+    '\u{2DE8}',
+    '\u{2DE9}',
+    '\u{2DEA}',
+    '\u{2DEB}',
+    '\u{2DEC}',
+    '\u{2DED}',
+    '\u{2DEE}',
+    '\u{2DEF}',
+    '\u{2DF0}',
+    '\u{2DF1}',
+    '\u{2DF2}',
+    '\u{2DF3}',
+    '\u{2DF4}',
+    '\u{2DF5}',
+    '\u{2DF6}',
+    '\u{2DF7}',
+    '\u{2DF8}',
+    '\u{2DF9}',
+    '\u{2DFA}',
+    '\u{2DFB}',
+    '\u{2DFC}',
+    '\u{2DFD}',
+    '\u{2DFE}',
+    '\u{2DFF}',
+    '\u{A66F}',
+    '\u{A67C}',
+    '\u{A67D}',
+    '\u{A6F0}',
+    '\u{A6F1}',
+    '\u{A8E0}',
+    '\u{A8E1}',
+    '\u{A8E2}',
+    '\u{A8E3}',
+    '\u{A8E4}',
+    '\u{A8E5}',
+    '\u{A8E6}',
+    '\u{A8E7}',
+    '\u{A8E8}',
+    '\u{A8E9}',
+    '\u{A8EA}',
+    '\u{A8EB}',
+    '\u{A8EC}',
+    '\u{A8ED}',
+    '\u{A8EE}',
+    '\u{A8EF}',
+    '\u{A8F0}',
+    '\u{A8F1}',
+    '\u{AAB0}',
+    '\u{AAB2}',
+    '\u{AAB3}',
+    '\u{AAB7}',
+    '\u{AAB8}',
+    '\u{AABE}',
+    '\u{AABF}',
+    '\u{AAC1}',
+    '\u{FE20}',
+    '\u{FE21}',
+    '\u{FE22}',
+    '\u{FE23}',
+    '\u{FE24}',
+    '\u{FE25}',
+    '\u{FE26}',
+    '\u{10A0F}',
+    '\u{10A38}',
+    '\u{1D185}',
+    '\u{1D186}',
+    '\u{1D187}',
+    '\u{1D188}',
+    '\u{1D189}',
+    '\u{1D1AA}',
+    '\u{1D1AB}',
+    '\u{1D1AC}',
+    '\u{1D1AD}',
+    '\u{1D242}',
+    '\u{1D243}',
+    '\u{1D244}',
+];
+
+impl KittyPrinter {
+    /// Print an animated image with the Kitty graphics protocol. All frames are sent once and
+    /// Kitty plays the animation by itself, honouring the delay of each frame.
+    ///
+    /// `loops` is the number of times the animation is played, or `None` to play it forever.
+    /// The returned [KittyImage] can be used to move or delete the animation.
+    /// ## Example
+    /// ```no_run
+    /// use image::AnimationDecoder;
+    /// use viuer::{Config, KittyPrinter};
+    ///
+    /// let file = std::fs::File::open("img.gif").expect("Could not open image.");
+    /// let decoder = image::codecs::gif::GifDecoder::new(file).expect("Could not decode GIF.");
+    /// let frames = decoder.into_frames().collect_frames().expect("Could not decode frames.");
+    /// KittyPrinter {}
+    ///     .print_animation(&mut std::io::stdout(), frames, None, &Config::default())
+    ///     .expect("Printing failed.");
+    /// ```
+    pub fn print_animation(
+        &self,
+        stdout: &mut impl Write,
+        frames: Vec<Frame>,
+        loops: Option<u32>,
+        config: &Config,
+    ) -> ViuResult<KittyImage> {
+        let mut frames = frames.into_iter();
+        let first = frames
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Animation has no frames"))?;
+
+        // the first frame is printed like any other image, becoming the root frame
+        let gap = frame_gap(&first);
+        let first = DynamicImage::ImageRgba8(first.into_buffer());
+        let (canvas_width, canvas_height) = first.dimensions();
+        let first = fill_box(&first, config);
+        let (shaped_width, shaped_height) = first.dimensions();
+        let placement = find_best_fit(&first, config);
+        let kitty_img = send_image(stdout, &first, config)?;
+        let id = kitty_img.id();
+        write_passthrough(
+            stdout,
+            &format!("\x1b_Ga=a,i={},r=1,z={},q=2\x1b\\", id, gap),
+        )?;
+
+        // images sized in pixels were resized, so the other frames have to be resized with them
+        let scale = |value: u32, size: u32, shaped_size: u32| {
+            if config.size_unit == SizeUnit::Pixels {
+                (u64::from(value) * u64::from(size) / u64::from(shaped_size)) as u32
+            } else {
+                value
+            }
+        };
+
+        for frame in frames {
+            let gap = frame_gap(&frame);
+            let (mut left, mut top) = (frame.left(), frame.top());
+            let mut img = DynamicImage::ImageRgba8(frame.into_buffer());
+            // the root frame was cropped or padded, so the other frames are drawn on a canvas
+            // of its original size and then shaped the same way
+            if matches!(config.fit_mode, FitMode::Cover | FitMode::Fill) {
+                let mut canvas = DynamicImage::new_rgba8(canvas_width, canvas_height);
+                image::imageops::overlay(&mut canvas, &img, left, top);
+                img = fill_box(&canvas, config).into_owned();
+                left = 0;
+                top = 0;
             }
-            KittySupport::Remote => {
-                // print through escape codes
-                print_remote(img, config)
+
+            let keys = format!(
+                "a=f,i={},x={},y={},z={},q=2",
+                id,
+                scale(left, placement.width, shaped_width),
+                scale(top, placement.height, shaped_height),
+                gap
+            );
+            if config.size_unit == SizeUnit::Pixels {
+                let width = std::cmp::max(1, scale(img.width(), placement.width, shaped_width));
+                let height = std::cmp::max(1, scale(img.height(), placement.height, shaped_height));
+                img = img.resize_exact(width, height, filter_type(config));
             }
+            transmit(
+                stdout,
+                &get_payload(&img, config.kitty_compress),
+                &keys,
+                true,
+            )?;
+        }
+
+        // v=1 loops forever, any higher value plays the animation v-1 times
+        let v = loops.map_or(1, |n| n.max(1).saturating_add(1));
+        write_passthrough(stdout, &format!("\x1b_Ga=a,i={},s=3,v={},q=2\x1b\\", id, v))?;
+        stdout.flush()?;
+
+        Ok(kitty_img)
+    }
+}
+
+// Delay of an animation frame in milliseconds
+fn frame_gap(frame: &Frame) -> u32 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    numer.checked_div(denom).unwrap_or(0)
+}
+
+// Get the payload of the image, and its size in cells. Images sized in pixels are resized to
+// that size, as that's how large Kitty displays them when no cells are given.
+fn get_sized_payload(img: &DynamicImage, config: &Config) -> (Payload, (u32, u32)) {
+    let placement = find_best_fit(img, config);
+    let payload = if config.size_unit == SizeUnit::Pixels {
+        let resized = img.resize_exact(placement.width, placement.height, filter_type(config));
+        get_payload(&resized, config.kitty_compress)
+    } else {
+        get_payload(img, config.kitty_compress)
+    };
+    (payload, (placement.columns, placement.rows))
+}
+
+// Image data in one of the formats Kitty understands, together with the keys describing it
+struct Payload {
+    data: Vec<u8>,
+    keys: String,
+}
+
+// Get the pixels of the image in a format Kitty understands. Images without an alpha channel are
+// sent as RGB, which is a quarter smaller. The data is compressed with zlib if requested.
+fn get_payload(img: &image::DynamicImage, compress: bool) -> Payload {
+    let (data, format) = if img.color().has_alpha() {
+        (img.to_rgba8().into_raw(), 32)
+    } else {
+        (img.to_rgb8().into_raw(), 24)
+    };
+    let mut keys = format!("f={},s={},v={}", format, img.width(), img.height());
+
+    let data = if compress {
+        keys.push_str(",o=z");
+        // level 6 is zlib's default, a good balance between speed and size
+        compress_to_vec_zlib(&data, 6)
+    } else {
+        data
+    };
+
+    Payload { data, keys }
+}
+
+// Print the payload with the method supported by the terminal
+fn print_payload(
+    stdout: &mut dyn Write,
+    mut payload: Payload,
+    mut size: (u32, u32),
+    config: &Config,
+    mut ids: Option<(u32, u32)>,
+) -> ViuResult<(u32, u32)> {
+    if config.kitty_placeholders {
+        // placeholders refer to the image by its id, so it always needs one
+        ids = Some((ids.map_or_else(next_id, |(id, _)| id), 0));
+        let max = PLACEHOLDER_DIACRITICS.len() as u32;
+        size = (size.0.min(max), size.1.min(max));
+        payload.keys.push_str(",U=1");
+    }
+
+    let config = &align(config, size);
+    adjust_offset(stdout, config)?;
+
+    // images sized in pixels are displayed with their own size, unless the placeholders need a
+    // grid of cells
+    let keys = if config.size_unit == SizeUnit::Pixels && !config.kitty_placeholders {
+        format!("a=T{}", id_keys(ids))
+    } else {
+        format!("a=T,c={},r={}{}", size.0, size.1, id_keys(ids))
+    };
+    transmit(stdout, &payload, &keys, ids.is_some())?;
+
+    match ids {
+        Some((id, _)) if config.kitty_placeholders => write_placeholders(stdout, size, id, config)?,
+        _ => writeln!(stdout)?,
+    }
+    stdout.flush()?;
+
+    Ok(size)
+}
+
+// Send the payload with the method supported by the terminal. The keys describe what Kitty
+// should do with it, and whether it responds.
+fn transmit(stdout: &mut dyn Write, payload: &Payload, keys: &str, quiet: bool) -> ViuResult {
+    match get_kitty_support() {
+        KittySupport::None => Err(ViuError::KittyNotSupported),
+        KittySupport::Local => {
+            // print from file
+            print_local(stdout, payload, keys, false)
+        }
+        KittySupport::SharedMemory => {
+            // print from shared memory
+            print_local(stdout, payload, keys, true)
+        }
+        KittySupport::Remote => {
+            // print through escape codes
+            print_remote(stdout, payload, keys, quiet)
+        }
+    }
+}
+
+// Write the placeholder cells of a virtual placement (U=1). Kitty draws the image wherever these
+// cells are, so they can be scrolled, reflowed, or stored by a multiplexer like any other text.
+fn write_placeholders(
+    stdout: &mut dyn Write,
+    (w, h): (u32, u32),
+    id: u32,
+    config: &Config,
+) -> ViuResult {
+    // the lower 24 bits of the image id are encoded in the foreground color, the upper 8 bits
+    // in a third diacritic
+    let color = format!(
+        "\x1b[38;2;{};{};{}m",
+        (id >> 16) & 0xff,
+        (id >> 8) & 0xff,
+        id & 0xff
+    );
+    let msb = match id >> 24 {
+        0 => String::new(),
+        msb => PLACEHOLDER_DIACRITICS[msb as usize].to_string(),
+    };
+
+    for (row, row_diacritic) in PLACEHOLDER_DIACRITICS.iter().take(h as usize).enumerate() {
+        // the first row starts where the offset was already adjusted to
+        if row > 0 && config.x > 0 {
+            stdout.execute(MoveRight(config.x))?;
+        }
+        write!(stdout, "{}", color)?;
+        for col_diacritic in PLACEHOLDER_DIACRITICS.iter().take(w as usize) {
+            write!(
+                stdout,
+                "{}{}{}{}",
+                PLACEHOLDER, row_diacritic, col_diacritic, msb
+            )?;
+        }
+        writeln!(stdout, "\x1b[39m")?;
+    }
+    Ok(())
+}
+
+/// Print an image with the Kitty graphics protocol, giving it an image and a placement id.
+///
+/// The returned [KittyImage] can be used to place the image again, move it or delete it,
+/// without sending the image data again.
+/// ## Example
+/// ```no_run
+/// use viuer::{print_kitty, Config, KittyDelete};
+///
+/// let img = image::open("img.jpg").expect("Could not open image.");
+/// let mut stdout = std::io::stdout();
+/// let kitty_img = print_kitty(&mut stdout, &img, &Config::default()).expect("Printing failed.");
+/// kitty_img.move_to(&mut stdout, 10, 5).expect("Moving failed.");
+/// kitty_img.delete(&mut stdout, KittyDelete::Image).expect("Deleting failed.");
+/// ```
+pub fn print_kitty(
+    stdout: &mut impl Write,
+    img: &image::DynamicImage,
+    config: &Config,
+) -> ViuResult<KittyImage> {
+    send_image(stdout, &fill_box(img, config), config)
+}
+
+// Same as print_kitty, for images which were already cropped or padded by fill_box
+fn send_image(
+    stdout: &mut impl Write,
+    img: &DynamicImage,
+    config: &Config,
+) -> ViuResult<KittyImage> {
+    let placement_id = if config.kitty_placeholders {
+        0
+    } else {
+        next_id()
+    };
+    let ids = (next_id(), placement_id);
+    let (payload, size) = get_sized_payload(img, config);
+    let (w, h) = print_payload(stdout, payload, size, config, Some(ids))?;
+
+    Ok(KittyImage {
+        id: ids.0,
+        placement_id: ids.1,
+        width: w,
+        height: h,
+    })
+}
+
+/// An image which was sent to Kitty with [print_kitty], and one of its placements.
+pub struct KittyImage {
+    id: u32,
+    placement_id: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// What to delete with [KittyImage::delete].
+pub enum KittyDelete {
+    /// All placements of the image, freeing the image data as well.
+    Image,
+    /// Only the placement of this handle. The image data is kept for other placements.
+    Placement,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// Which images to delete with [clear_kitty].
+pub enum KittyClear {
+    /// All images which intersect with the current cursor position.
+    AtCursor,
+    /// All images visible on the screen.
+    All,
+}
+
+impl KittyImage {
+    /// Id of the image in Kitty.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Id of the placement this handle refers to. This is 0 for images printed with
+    /// [Config::kitty_placeholders](crate::Config::kitty_placeholders), whose placement is
+    /// chosen by Kitty.
+    pub fn placement_id(&self) -> u32 {
+        self.placement_id
+    }
+
+    /// Dimensions of the placement in terminal cells.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Display the image one more time, creating a new placement. Offsets are taken from the
+    /// config. If no width or height are given, the size of this placement is used.
+    pub fn place(&self, stdout: &mut impl Write, config: &Config) -> ViuResult<KittyImage> {
+        let (w, h) = match (config.width, config.height) {
+            (None, None) => (self.width, self.height),
+            // keep the aspect ratio of this placement
+            (Some(w), None) => (w, std::cmp::max(1, self.height * w / self.width)),
+            (None, Some(h)) => (std::cmp::max(1, self.width * h / self.height), h),
+            (Some(w), Some(h)) => (w, h),
+        };
+        let placement = KittyImage {
+            id: self.id,
+            placement_id: next_id(),
+            width: w,
+            height: h,
+        };
+
+        adjust_offset(stdout, &align(config, (w, h)))?;
+        write_passthrough(
+            stdout,
+            &format!(
+                "\x1b_Ga=p,i={},p={},c={},r={},q=2\x1b\\",
+                placement.id, placement.placement_id, w, h
+            ),
+        )?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+
+        Ok(placement)
+    }
+
+    /// Move this placement so that its top left corner is at (x, y), in terminal cells from the
+    /// top left corner of the terminal. The cursor is not moved.
+    ///
+    /// Images printed with placeholders move with the text instead, so an error is returned
+    /// for them.
+    pub fn move_to(&self, stdout: &mut impl Write, x: u16, y: u16) -> ViuResult {
+        if self.placement_id == 0 {
+            return Err(ViuError::InvalidConfiguration(
+                "images printed with placeholders cannot be moved".to_owned(),
+            ));
         }
+
+        // a placement with an existing id replaces the old one
+        execute!(stdout, SavePosition, MoveTo(x, y))?;
+        write_passthrough(
+            stdout,
+            &format!(
+                "\x1b_Ga=p,i={},p={},c={},r={},C=1,q=2\x1b\\",
+                self.id, self.placement_id, self.width, self.height
+            ),
+        )?;
+        execute!(stdout, RestorePosition)?;
+        Ok(())
     }
 
-    // TODO: guess_format() here in order to treat PNGs specially (f=100).
-    // Also, maybe get channel count and use f=24 or f=32 accordingly.
-    // fn print_from_file(&self, filename: &str, config: &Config) -> ViuResult<(u32, u32)> {}
+    /// Delete this image or placement from the screen, see [KittyDelete].
+    pub fn delete(&self, stdout: &mut impl Write, target: KittyDelete) -> ViuResult {
+        // uppercase values also free the image data, if it is not used anymore
+        let command = match target {
+            KittyDelete::Image => format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", self.id),
+            // the placement of placeholders has no id, but it is the only one of the image
+            KittyDelete::Placement if self.placement_id == 0 => {
+                format!("\x1b_Ga=d,d=i,i={},q=2\x1b\\", self.id)
+            }
+            KittyDelete::Placement => format!(
+                "\x1b_Ga=d,d=i,i={},p={},q=2\x1b\\",
+                self.id, self.placement_id
+            ),
+        };
+        write_passthrough(stdout, &command)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Delete Kitty images from the screen, regardless of who printed them, see [KittyClear].
+/// The image data is freed as well, if it is not used anymore.
+pub fn clear_kitty(stdout: &mut impl Write, target: KittyClear) -> ViuResult {
+    let command = match target {
+        KittyClear::AtCursor => "\x1b_Ga=d,d=C,q=2\x1b\\",
+        KittyClear::All => "\x1b_Ga=d,d=A,q=2\x1b\\",
+    };
+    write_passthrough(stdout, command)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Get a new id for an image or a placement. Ids are derived from the process id, so that
+// different processes are unlikely to replace each other's images.
+fn next_id() -> u32 {
+    lazy_static! {
+        static ref NEXT_ID: AtomicU32 = AtomicU32::new(std::process::id().wrapping_mul(1 << 16));
+    }
+    loop {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        // 0 is not a valid id
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+// Keys which give ids to an image and its placement. Kitty responds to commands with ids, so
+// the responses are turned off.
+fn id_keys(ids: Option<(u32, u32)>) -> String {
+    match ids {
+        // placement id 0 lets Kitty pick one, as placeholders can only refer to it by image
+        Some((id, 0)) => format!(",i={},q=2", id),
+        Some((id, placement_id)) => format!(",i={},p={},q=2", id, placement_id),
+        None => String::new(),
+    }
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -45,22 +822,31 @@ pub enum KittySupport {
     None,
     /// Kitty is running locally, data can be shared through a file.
     Local,
+    /// Kitty is running locally, data can be shared through POSIX shared memory.
+    /// Preferred over [KittySupport::Local], as nothing is written to the filesystem.
+    SharedMemory,
     /// Kitty is not running locally, data has to be sent through escape codes.
     Remote,
 }
 
 // Check if Kitty protocol can be used
 fn check_kitty_support() -> KittySupport {
-    if let Ok(term) = std::env::var("TERM") {
-        if term.contains("kitty") {
-            if has_local_support().is_ok() {
-                return KittySupport::Local;
-            } else {
-                return KittySupport::Remote;
-            }
-        }
+    // KITTY_WINDOW_ID is inherited by multiplexers running inside Kitty
+    let is_kitty = utils::terminal_name().is_some_and(|term| term.contains("kitty"))
+        || std::env::var_os("KITTY_WINDOW_ID").is_some();
+    if !is_kitty {
+        KittySupport::None
+    } else if utils::multiplexer() != Multiplexer::None {
+        // multiplexers don't reliably pass the responses to queries back, so the image data is
+        // sent with escape codes, which always work
+        KittySupport::Remote
+    } else if has_shared_memory_support().is_ok() {
+        KittySupport::SharedMemory
+    } else if has_local_support().is_ok() {
+        KittySupport::Local
+    } else {
+        KittySupport::Remote
     }
-    KittySupport::None
 }
 
 // Query the terminal whether it can display an image from a file
@@ -70,14 +856,35 @@ fn has_local_support() -> ViuResult {
     let raw_img = x.as_raw();
     let path = store_in_tmp_file(raw_img)?;
 
+    // t=t tells Kitty it's reading from a temp file and will delete if afterwards
+    query_support(
+        "t",
+        path.to_str()
+            .ok_or_else(|| std::io::Error::other("Could not convert path to &str"))?,
+    )
+}
+
+// Query the terminal whether it can display an image from shared memory
+fn has_shared_memory_support() -> ViuResult {
+    // create a shared memory object that will hold a 1x1 image
+    let x = image::RgbaImage::new(1, 1);
+    let name = store_in_shm(x.as_raw())?;
+
+    let result = query_support("s", &name);
+    // Kitty removes the object after reading it, but it has to be cleaned up if that failed
+    if result.is_err() {
+        remove_shm(&name);
+    }
+    result
+}
+
+// Ask Kitty to load a 1x1 image through the given transmission medium, without displaying it
+fn query_support(medium: &str, location: &str) -> ViuResult {
     // send the query
     print!(
-        // t=t tells Kitty it's reading from a temp file and will delete if afterwards
-        "\x1b_Gi=31,s=1,v=1,a=q,t=t;{}\x1b\\",
-        base64::encode(path.to_str().ok_or_else(|| std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Could not convert path to &str"
-        ))?)
+        "\x1b_Gi=31,s=1,v=1,a=q,t={};{}\x1b\\",
+        medium,
+        base64::encode(location)
     );
     std::io::stdout().flush()?;
 
@@ -109,72 +916,136 @@ fn has_local_support() -> ViuResult {
     Err(ViuError::KittyResponse(response))
 }
 
-// Print with kitty graphics protocol through a temp file
-// TODO: try with kitty's supported compression
-fn print_local(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-    let rgba = img.to_rgba8();
-    let raw_img = rgba.as_raw();
-    let path = store_in_tmp_file(raw_img)?;
-
-    let mut stdout = std::io::stdout();
-    adjust_offset(&mut stdout, config)?;
-
-    // get the desired width and height
-    let (w, h) = find_best_fit(&img, config.width, config.height);
+// Print with kitty graphics protocol through a temp file, or shared memory if requested
+fn print_local(
+    stdout: &mut dyn Write,
+    payload: &Payload,
+    keys: &str,
+    shared_memory: bool,
+) -> ViuResult {
+    let (medium, location) = if shared_memory {
+        ("s", store_in_shm(&payload.data)?)
+    } else {
+        let path = store_in_tmp_file(&payload.data)?;
+        let path = path
+            .to_str()
+            .ok_or_else(|| ViuError::IO(Error::other("Could not convert path to &str")))?
+            .to_owned();
+        ("t", path)
+    };
 
-    write!(
+    write_passthrough(
         stdout,
-        "\x1b_Gf=32,s={},v={},c={},r={},a=T,t=t;{}\x1b\\",
-        img.width(),
-        img.height(),
-        w,
-        h,
-        base64::encode(path.to_str().ok_or_else(|| ViuError::IO(Error::new(
-            ErrorKind::Other,
-            "Could not convert path to &str"
-        )))?)
+        &format!(
+            "\x1b_G{},{},t={};{}\x1b\\",
+            payload.keys,
+            keys,
+            medium,
+            base64::encode(location)
+        ),
     )?;
-    writeln!(stdout)?;
-    stdout.flush()?;
-
-    Ok((w, h))
+    Ok(())
 }
 
 // Print with escape codes
-// TODO: try compression
-fn print_remote(img: &image::DynamicImage, config: &Config) -> ViuResult<(u32, u32)> {
-    let rgba = img.to_rgba8();
-    let raw = rgba.as_raw();
-    let encoded = base64::encode(raw);
+fn print_remote(stdout: &mut dyn Write, payload: &Payload, keys: &str, quiet: bool) -> ViuResult {
+    let encoded = base64::encode(&payload.data);
     let mut iter = encoded.chars().peekable();
 
-    let mut stdout = std::io::stdout();
-    adjust_offset(&mut stdout, config)?;
-
-    let (w, h) = find_best_fit(&img, config.width, config.height);
-
     let first_chunk: String = iter.by_ref().take(4096).collect();
+    let m = if iter.peek().is_some() { 1 } else { 0 };
 
     // write the first chunk, which describes the image
-    write!(
+    write_passthrough(
         stdout,
-        "\x1b_Gf=32,a=T,t=d,s={},v={},c={},r={},m=1;{}\x1b\\",
-        img.width(),
-        img.height(),
-        w,
-        h,
-        first_chunk
+        &format!(
+            "\x1b_G{},{},t=d,m={};{}\x1b\\",
+            payload.keys, keys, m, first_chunk
+        ),
     )?;
 
+    // subsequent chunks only need the quiet key, if any
+    let quiet = if quiet { ",q=2" } else { "" };
+
     // write all the chunks, each containing 4096 bytes of data
     while iter.peek().is_some() {
         let chunk: String = iter.by_ref().take(4096).collect();
         let m = if iter.peek().is_some() { 1 } else { 0 };
-        write!(stdout, "\x1b_Gm={};{}\x1b\\", m, chunk)?;
+        write_passthrough(stdout, &format!("\x1b_Gm={}{};{}\x1b\\", m, quiet, chunk))?;
     }
-    writeln!(stdout)?;
-    stdout.flush()?;
-    Ok((w, h))
+    Ok(())
+}
+
+// Create a POSIX shared memory object and write the byte slice to it. Returns the name of the
+// object. Like temp files, Kitty removes it after reading.
+#[cfg(unix)]
+fn store_in_shm(buf: &[u8]) -> ViuResult<String> {
+    let name = format!("/viuer-{}-{}", std::process::id(), next_id());
+    let c_name = CString::new(name.as_str()).map_err(Error::other)?;
+
+    // SAFETY: the name is a valid C string, and the mapping is exactly as long as the buffer,
+    // which is what the object is truncated to
+    unsafe {
+        // shm_open is variadic on macOS, where mode_t is too small to be passed as it is
+        let fd = libc::shm_open(
+            c_name.as_ptr(),
+            libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+            0o600 as libc::c_uint,
+        );
+        if fd < 0 {
+            return Err(Error::last_os_error().into());
+        }
+
+        let result = if libc::ftruncate(fd, buf.len() as libc::off_t) != 0 {
+            Err(Error::last_os_error())
+        } else {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                buf.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                Err(Error::last_os_error())
+            } else {
+                std::ptr::copy_nonoverlapping(buf.as_ptr(), ptr as *mut u8, buf.len());
+                libc::munmap(ptr, buf.len());
+                Ok(())
+            }
+        };
+        libc::close(fd);
+
+        if let Err(e) = result {
+            libc::shm_unlink(c_name.as_ptr());
+            return Err(e.into());
+        }
+    }
+
+    Ok(name)
+}
+
+#[cfg(not(unix))]
+fn store_in_shm(_buf: &[u8]) -> ViuResult<String> {
+    Err(Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Shared memory is not supported on this platform",
+    )
+    .into())
+}
+
+// Remove a shared memory object created with store_in_shm
+fn remove_shm(name: &str) {
+    #[cfg(unix)]
+    if let Ok(c_name) = CString::new(name) {
+        // SAFETY: the name is a valid C string
+        unsafe {
+            libc::shm_unlink(c_name.as_ptr());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = name;
 }
 
 // Create a file in temporary dir and write the byte slice to it.
@@ -191,3 +1062,203 @@ fn store_in_tmp_file(buf: &[u8]) -> std::result::Result<std::path::PathBuf, ViuE
     tmpfile.flush()?;
     Ok(path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> KittyImage {
+        KittyImage {
+            id: 7,
+            placement_id: 3,
+            width: 40,
+            height: 20,
+        }
+    }
+
+    #[test]
+    fn test_get_payload() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            10,
+            10,
+            image::Rgba([1, 2, 3, 4]),
+        ));
+
+        let payload = get_payload(&img, false);
+        assert_eq!(payload.data.len(), 400);
+        assert_eq!(payload.keys, "f=32,s=10,v=10");
+
+        let payload = get_payload(&img, true);
+        assert!(payload.data.len() < 400);
+        assert_eq!(payload.keys, "f=32,s=10,v=10,o=z");
+        let decompressed = miniz_oxide::inflate::decompress_to_vec_zlib(&payload.data).unwrap();
+        assert_eq!(decompressed, img.to_rgba8().into_raw());
+
+        // no alpha channel
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(10, 10));
+        let payload = get_payload(&img, false);
+        assert_eq!(payload.data.len(), 300);
+        assert_eq!(payload.keys, "f=24,s=10,v=10");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_in_shm() {
+        let name = store_in_shm(&[1, 2, 3, 4]).unwrap();
+        let c_name = CString::new(name.as_str()).unwrap();
+
+        // SAFETY: the name is a valid C string
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0 as libc::c_uint) };
+        assert!(fd >= 0);
+        unsafe { libc::close(fd) };
+
+        remove_shm(&name);
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0 as libc::c_uint) };
+        assert!(fd < 0);
+    }
+
+    #[test]
+    fn test_frame_gap() {
+        let frame = Frame::from_parts(
+            image::RgbaImage::new(1, 1),
+            0,
+            0,
+            image::Delay::from_numer_denom_ms(250, 2),
+        );
+        assert_eq!(frame_gap(&frame), 125);
+    }
+
+    #[test]
+    fn test_print_remote() {
+        let payload = Payload {
+            data: vec![0; 3],
+            keys: String::from("f=24,s=1,v=1"),
+        };
+        let mut vec = Vec::new();
+        print_remote(&mut vec, &payload, "a=T", true).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&vec).unwrap(),
+            "\x1b_Gf=24,s=1,v=1,a=T,t=d,m=0;AAAA\x1b\\"
+        );
+
+        // 4096 base64 characters fit in the first chunk, the rest goes in the last one
+        let payload = Payload {
+            data: vec![0; 3075],
+            keys: String::from("f=24,s=1025,v=1"),
+        };
+        let mut vec = Vec::new();
+        print_remote(&mut vec, &payload, "a=T", true).unwrap();
+        let expected = format!(
+            "\x1b_Gf=24,s=1025,v=1,a=T,t=d,m=1;{}\x1b\\\x1b_Gm=0,q=2;AAAA\x1b\\",
+            "A".repeat(4096)
+        );
+        assert_eq!(std::str::from_utf8(&vec).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_placeholders() {
+        let config = Config {
+            x: 1,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        write_placeholders(&mut buf, (2, 2), 0x0201_0203, &config).unwrap();
+
+        let cell = |row: usize, col: usize| {
+            format!(
+                "\u{10EEEE}{}{}\u{030E}",
+                PLACEHOLDER_DIACRITICS[row], PLACEHOLDER_DIACRITICS[col]
+            )
+        };
+        let expected = format!(
+            "\x1b[38;2;1;2;3m{}{}\x1b[39m\n\x1b[1C\x1b[38;2;1;2;3m{}{}\x1b[39m\n",
+            cell(0, 0),
+            cell(0, 1),
+            cell(1, 0),
+            cell(1, 1)
+        );
+        assert_eq!(std::str::from_utf8(&buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_kitty_image_delete() {
+        let placeholder = KittyImage {
+            placement_id: 0,
+            ..test_image()
+        };
+        let cases = [
+            (
+                test_image(),
+                KittyDelete::Image,
+                "\x1b_Ga=d,d=I,i=7,q=2\x1b\\",
+            ),
+            (
+                test_image(),
+                KittyDelete::Placement,
+                "\x1b_Ga=d,d=i,i=7,p=3,q=2\x1b\\",
+            ),
+            (
+                placeholder,
+                KittyDelete::Placement,
+                "\x1b_Ga=d,d=i,i=7,q=2\x1b\\",
+            ),
+        ];
+        for (img, target, expected) in cases.iter() {
+            let mut vec = Vec::new();
+            img.delete(&mut vec, *target).unwrap();
+            assert_eq!(std::str::from_utf8(&vec).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_clear_kitty() {
+        let cases = [
+            (KittyClear::AtCursor, "\x1b_Ga=d,d=C,q=2\x1b\\"),
+            (KittyClear::All, "\x1b_Ga=d,d=A,q=2\x1b\\"),
+        ];
+        for (target, expected) in cases.iter() {
+            let mut vec = Vec::new();
+            clear_kitty(&mut vec, *target).unwrap();
+            assert_eq!(std::str::from_utf8(&vec).unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_kitty_image_move_to() {
+        let mut vec = Vec::new();
+        test_image().move_to(&mut vec, 4, 2).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&vec).unwrap(),
+            "\x1b7\x1b[3;5H\x1b_Ga=p,i=7,p=3,c=40,r=20,C=1,q=2\x1b\\\x1b8"
+        );
+
+        // placeholders can't be moved, and nothing is written for them
+        let placeholder = KittyImage {
+            placement_id: 0,
+            ..test_image()
+        };
+        let mut vec = Vec::new();
+        assert!(placeholder.move_to(&mut vec, 4, 2).is_err());
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn test_kitty_image_place() {
+        let config = Config {
+            width: Some(20),
+            absolute_offset: false,
+            ..Default::default()
+        };
+        let mut vec = Vec::new();
+        let placement = test_image().place(&mut vec, &config).unwrap();
+
+        assert_eq!(placement.id(), 7);
+        assert_ne!(placement.placement_id(), 3);
+        assert_eq!(placement.size(), (20, 10));
+        let expected = format!(
+            "\x1b_Ga=p,i=7,p={},c=20,r=10,q=2\x1b\\\n",
+            placement.placement_id()
+        );
+        assert_eq!(std::str::from_utf8(&vec).unwrap(), expected);
+    }
+}
@@ -1,75 +1,483 @@
 use crate::config::Config;
 use crate::error::{ViuError, ViuResult};
-use crate::utils::terminal_size;
+use crate::utils::{self, terminal_size};
 use crossterm::cursor::{MoveRight, MoveTo, MoveToPreviousLine};
-use crossterm::execute;
+use crossterm::ExecutableCommand;
+use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
 use std::io::Write;
 
+// Size of a terminal cell in pixels when the terminal does not report it
+const DEFAULT_CELL_SIZE: (u32, u32) = (10, 20);
+
 mod block;
-pub use block::BlockPrinter;
+pub use block::{BlockCanvas, BlockMode, BlockPrinter};
+
+mod braille;
+pub use braille::BraillePrinter;
+
+mod ascii;
+pub use ascii::AsciiPrinter;
 
 mod kitty;
-pub use kitty::{get_kitty_support, KittyPrinter, KittySupport};
+pub use kitty::{
+    clear_kitty, get_kitty_support, print_kitty, KittyClear, KittyDelete, KittyImage, KittyPrinter,
+    KittySupport,
+};
 
-#[cfg(feature = "sixel")]
 mod sixel;
-#[cfg(feature = "sixel")]
-pub use self::sixel::{is_sixel_supported, SixelPrinter};
+pub use self::sixel::{is_sixel_supported, Quantizer, SixelPrinter};
 
 mod iterm;
 pub use iterm::iTermPrinter;
 pub use iterm::is_iterm_supported;
 
 pub trait Printer {
-    // Print the given image in the terminal while respecting the options in the config struct.
+    // Print the given image to the writer while respecting the options in the config struct.
     // Return the dimensions of the printed image in **terminal cells**.
-    fn print(&self, img: &DynamicImage, config: &Config) -> ViuResult<(u32, u32)>;
-    fn print_from_file(&self, filename: &str, config: &Config) -> ViuResult<(u32, u32)> {
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)>;
+    fn print_from_file(
+        &self,
+        stdout: &mut dyn Write,
+        filename: &str,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
         let img = image::io::Reader::open(filename)?
             .with_guessed_format()?
             .decode()?;
-        self.print(&img, config)
+        self.print(stdout, &img, config)
     }
 }
 
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// Unit of the width and height in [Config].
+pub enum SizeUnit {
+    /// Terminal cells.
+    Cells,
+    /// Pixels. Graphics protocols print images of this exact size, other printers use the
+    /// amount of cells that holds this many pixels.
+    Pixels,
+    /// Percent of the terminal's width and height.
+    Percent,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// How an image is fitted in the box given by the width and height in [Config]. When neither
+/// is given, the box is the terminal.
+pub enum FitMode {
+    /// Scale the image up or down until it fits in the box, preserving its aspect ratio.
+    Contain,
+    /// Scale the image until it covers the whole box, preserving its aspect ratio. The parts
+    /// which don't fit are cropped.
+    Cover,
+    /// Stretch the image to the width and height, ignoring its aspect ratio. If only one of them
+    /// is given, or none, the image behaves like with [FitMode::ScaleDown].
+    Stretch,
+    /// Fill the whole box, scaling the image like [FitMode::Contain] and padding the rest with
+    /// transparent pixels.
+    Fill,
+    /// Like [FitMode::Contain], but small images are never scaled up.
+    ScaleDown,
+    /// Scale the image by a whole number, or divide it by one if it's too large, to keep the
+    /// pixels sharp. Meant for pixel art, which is resized without any smoothing.
+    Integer,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// Where an image is placed in the box given by the width and height in [Config], or in the
+/// terminal if they are not given. The x and y offsets are added to the aligned position.
+/// ## Example
+/// The snippet below prints "img.jpg" in the center of the terminal.
+/// ```no_run
+/// use viuer::{print_from_file, Align, Config};
+///
+/// let config = Config {
+///     align_x: Align::Center,
+///     align_y: Align::Center,
+///     ..Default::default()
+/// };
+/// print_from_file("img.jpg", &config).expect("Image printing failed.");
+/// ```
+pub enum Align {
+    /// Align the image to the left or top.
+    Start,
+    /// Center the image.
+    Center,
+    /// Align the image to the right or bottom.
+    End,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+/// Size of an image when it is printed, both in terminal cells and in pixels.
+pub struct Placement {
+    /// Width in terminal cells.
+    pub columns: u32,
+    /// Height in terminal cells.
+    pub rows: u32,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
 /// Resize a [image::DynamicImage] so that it fits within optional width and height bounds.
 /// If none are provided, terminal size is used instead.
 pub fn resize(img: &DynamicImage, width: Option<u32>, height: Option<u32>) -> DynamicImage {
-    let (w, h) = find_best_fit(img, width, height);
+    // half blocks display 1x2 pixels in a single cell
+    let (w, h) = fit_in_cells(
+        img.dimensions(),
+        width,
+        height,
+        (1, 2),
+        detected_cell_size(),
+        FitMode::Stretch,
+    );
+    img.resize_exact(w, 2 * h, FilterType::Triangle)
+}
+
+// Same as resize, but for printers which display cell_pixels (columns, rows) of the image
+// in a single terminal cell. The bounds and cell size are taken from the config.
+pub(crate) fn resize_for_cells(
+    img: &DynamicImage,
+    config: &Config,
+    cell_pixels: (u32, u32),
+) -> DynamicImage {
+    let placement = find_best_fit_for_cells(img, config, cell_pixels);
+
+    // find_best_fit returns values in terminal cells. Hence, we multiply by the amount of
+    // pixels a cell can hold, e.g. a 5x10 image can fit in 5x5 cells of half blocks.
+    img.resize_exact(
+        placement.columns * cell_pixels.0,
+        placement.rows * cell_pixels.1,
+        filter_type(config),
+    )
+}
+
+// Filter used to resize images. Pixel art scaled by whole numbers has to stay sharp.
+pub(crate) fn filter_type(config: &Config) -> FilterType {
+    if config.fit_mode == FitMode::Integer {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    }
+}
+
+// Crop or pad the image to the shape of the box it is printed in, for the fit modes that fill
+// the whole box. Other images are returned as they are.
+pub(crate) fn fill_box<'a>(img: &'a DynamicImage, config: &Config) -> Cow<'a, DynamicImage> {
+    let (box_width, box_height) = match box_in_pixels(config) {
+        Some(size) if matches!(config.fit_mode, FitMode::Cover | FitMode::Fill) => size,
+        _ => return Cow::Borrowed(img),
+    };
+
+    let (width, height) = img.dimensions();
+    let (w, h) = (u64::from(width), u64::from(height));
+    // whether the image is wider than the box
+    let wider = w * box_height > h * box_width;
+
+    if config.fit_mode == FitMode::Cover {
+        let (crop_w, crop_h) = if wider {
+            (h * box_width / box_height, h)
+        } else {
+            (w, w * box_height / box_width)
+        };
+        let (crop_w, crop_h) = (
+            std::cmp::max(1, crop_w) as u32,
+            std::cmp::max(1, crop_h) as u32,
+        );
+        Cow::Owned(img.crop_imm((width - crop_w) / 2, (height - crop_h) / 2, crop_w, crop_h))
+    } else {
+        let (pad_w, pad_h) = if wider {
+            (w, w * box_height / box_width)
+        } else {
+            (h * box_width / box_height, h)
+        };
+        let mut padded = DynamicImage::new_rgba8(pad_w as u32, pad_h as u32);
+        image::imageops::overlay(
+            &mut padded,
+            img,
+            (pad_w as u32 - width) / 2,
+            (pad_h as u32 - height) / 2,
+        );
+        Cow::Owned(padded)
+    }
+}
+
+// Size in pixels of the box the image is printed in, if both of its dimensions are known. Empty
+// boxes are made a pixel wide or high, as there is nothing to fill otherwise.
+fn box_in_pixels(config: &Config) -> Option<(u64, u64)> {
+    let (cell_width, cell_height) = cell_size(config);
+    let (width, height) = match config.size_unit {
+        SizeUnit::Pixels => (config.width, config.height),
+        _ => {
+            let (width, height) = bounds_in_cells(config);
+            (
+                width.map(|w| w * cell_width),
+                height.map(|h| h * cell_height),
+            )
+        }
+    };
+
+    match (width, height) {
+        (Some(w), Some(h)) => Some((u64::from(w).max(1), u64::from(h).max(1))),
+        (None, None) => {
+            let (columns, rows) = terminal_box();
+            Some((
+                u64::from(columns * cell_width),
+                u64::from(rows * cell_height),
+            ))
+        }
+        _ => None,
+    }
+}
+
+// Width and height from the config in cells, when they are not given in pixels
+fn bounds_in_cells(config: &Config) -> (Option<u32>, Option<u32>) {
+    match config.size_unit {
+        SizeUnit::Percent => {
+            let (term_w, term_h) = terminal_size();
+            let percent = |size: u16, p: u32| std::cmp::max(1, u32::from(size) * p / 100);
+            (
+                config.width.map(|w| percent(term_w, w)),
+                config.height.map(|h| percent(term_h, h)),
+            )
+        }
+        _ => (config.width, config.height),
+    }
+}
+
+// Apply the alignment to the offsets of the config, for an image of the given size in cells
+pub(crate) fn align(config: &Config, (columns, rows): (u32, u32)) -> Cow<'_, Config> {
+    if config.align_x == Align::Start && config.align_y == Align::Start {
+        return Cow::Borrowed(config);
+    }
+
+    // a dimension which is not given is aligned in the same space images are fitted in
+    let (term_w, term_h) = terminal_box();
+    let (width, height) = match config.size_unit {
+        SizeUnit::Pixels => {
+            let (cell_width, cell_height) = cell_size(config);
+            (
+                config.width.map(|w| w.div_ceil(cell_width)),
+                config.height.map(|h| h.div_ceil(cell_height)),
+            )
+        }
+        _ => bounds_in_cells(config),
+    };
+    let width = width.unwrap_or(term_w);
+    let height = height.unwrap_or(term_h);
+
+    let shift = |align: Align, free: u32| match align {
+        Align::Start => 0,
+        Align::Center => free / 2,
+        Align::End => free,
+    };
+    let x = shift(config.align_x, width.saturating_sub(columns));
+    let y = shift(config.align_y, height.saturating_sub(rows));
+
+    Cow::Owned(Config {
+        x: config.x.saturating_add(x.min(u32::from(u16::MAX)) as u16),
+        y: config.y.saturating_add(y.min(i16::MAX as u32) as i16),
+        ..config.clone()
+    })
+}
 
-    // find_best_fit returns values in terminal cells. Hence, we multiply by two
-    // because a 5x10 image can fit in 5x5 cells.
-    img.resize_exact(w, 2 * h, image::imageops::FilterType::Triangle)
+// The terminal, as a box in cells. One row is left for the prompt after printing.
+fn terminal_box() -> (u32, u32) {
+    let (term_w, term_h) = terminal_size();
+    (u32::from(term_w), std::cmp::max(1, u32::from(term_h) - 1))
+}
+
+// Size of a terminal cell in pixels. The aspect ratio from the config is preferred over the
+// detected one.
+fn cell_size(config: &Config) -> (u32, u32) {
+    let (w, h) = detected_cell_size();
+    match config.cell_aspect_ratio {
+        Some(ratio) if ratio > 0.0 => (w, std::cmp::max(1, (w as f32 * ratio).round() as u32)),
+        _ => (w, h),
+    }
+}
+
+// Size of a terminal cell reported by the terminal. If it is unknown, a cell is assumed to be
+// twice as tall as it is wide.
+fn detected_cell_size() -> (u32, u32) {
+    match utils::cell_size() {
+        Some((w, h)) => (u32::from(w), u32::from(h)),
+        None => DEFAULT_CELL_SIZE,
+    }
 }
 
 /// Find the best dimensions for the printed image, based on user's input.
-/// Returns the dimensions of how the image should be printed in **terminal cells**.
+/// Returns the size of the printed image in **terminal cells** and in pixels.
 ///
 /// The behaviour is different based on the provided width and height:
 /// - If both are None, the image will be resized to fit in the terminal. Aspect ratio is preserved.
 /// - If only one is provided and the other is None, it will fit the image in the provided boundary. Aspect ratio is preserved.
 /// - If both are provided, the image will be resized to match the new size. Aspect ratio is **not** preserved.
 ///
-/// Example:
+/// ## Example
 /// Use None for both dimensions to use terminal size (80x24) instead.
 /// The image ratio is 2:1, the terminal can be split into 80x46 squares.
 /// The best fit would be to use the whole width (80) and 40 vertical squares,
 /// which is equivalent to 20 terminal cells.
+/// ```no_run
+/// use viuer::{find_best_fit, Config};
 ///
 /// let img = image::DynamicImage::ImageRgba8(image::RgbaImage::new(160, 80));
-/// let (w, h) = find_best_fit(&img, None, None);
-/// assert_eq!(w, 80);
-/// assert_eq!(h, 20);
-//TODO: it might make more sense to change signiture from img to (width, height)
-fn find_best_fit(img: &DynamicImage, width: Option<u32>, height: Option<u32>) -> (u32, u32) {
-    let (img_width, img_height) = img.dimensions();
+/// let placement = find_best_fit(&img, &Config::default());
+/// println!("{}x{} cells", placement.columns, placement.rows);
+/// ```
+pub fn find_best_fit(img: &DynamicImage, config: &Config) -> Placement {
+    find_best_fit_for_cells(img, config, (1, 2))
+}
+
+// Same as find_best_fit, but for printers which display cell_pixels (columns, rows) of the
+// image in a single terminal cell. Only the amount of columns matters: the shape of a cell
+// still comes from its size, but an image that is smaller than the bounds can be printed in
+// fewer cells before it has to be scaled up.
+fn find_best_fit_for_cells(
+    img: &DynamicImage,
+    config: &Config,
+    cell_pixels: (u32, u32),
+) -> Placement {
+    find_best_fit_for_size(img.dimensions(), config, cell_pixels)
+}
+
+// Same as find_best_fit_for_cells, but only needs the dimensions of the image. Useful when the
+// image does not have to be decoded.
+fn find_best_fit_for_size(
+    img_size: (u32, u32),
+    config: &Config,
+    cell_pixels: (u32, u32),
+) -> Placement {
+    let (cell_width, cell_height) = cell_size(config);
+
+    if config.size_unit == SizeUnit::Pixels {
+        let (width, height) = fit_in_pixels(
+            img_size,
+            config.width,
+            config.height,
+            (cell_width, cell_height),
+            config.fit_mode,
+        );
+        return Placement {
+            columns: std::cmp::max(1, width.div_ceil(cell_width)),
+            rows: std::cmp::max(1, height.div_ceil(cell_height)),
+            width,
+            height,
+        };
+    }
+
+    let (width, height) = bounds_in_cells(config);
+    let (columns, rows) = fit_in_cells(
+        img_size,
+        width,
+        height,
+        cell_pixels,
+        (cell_width, cell_height),
+        config.fit_mode,
+    );
+    Placement {
+        columns,
+        rows,
+        width: columns * cell_width,
+        height: rows * cell_height,
+    }
+}
+
+// Fit the image in bounds given in pixels. Without bounds, the terminal is used. Like fitting in
+// cells, with the Stretch and ScaleDown modes images are not scaled up.
+fn fit_in_pixels(
+    (img_width, img_height): (u32, u32),
+    width: Option<u32>,
+    height: Option<u32>,
+    (cell_width, cell_height): (u32, u32),
+    mode: FitMode,
+) -> (u32, u32) {
+    let (width, height) = match (width, height) {
+        (None, None) => {
+            let (columns, rows) = terminal_box();
+            (Some(columns * cell_width), Some(rows * cell_height))
+        }
+        bounds => bounds,
+    };
+
+    if !matches!(mode, FitMode::Stretch | FitMode::ScaleDown) {
+        let natural = (f64::from(img_width), f64::from(img_height));
+        return scale_to_box(natural, (width, height), mode);
+    }
+
+    let (img_width, img_height) = (u64::from(img_width), u64::from(img_height));
+    let fit = |bound_width: u64, bound_height: u64| {
+        if img_width <= bound_width && img_height <= bound_height {
+            (img_width, img_height)
+        } else if bound_width * img_height <= bound_height * img_width {
+            (bound_width, img_height * bound_width / img_width)
+        } else {
+            (img_width * bound_height / img_height, bound_height)
+        }
+    };
+
+    let (w, h) = match (width, height) {
+        (Some(w), None) => fit(u64::from(w), img_height),
+        (None, Some(h)) => fit(img_width, u64::from(h)),
+        (Some(w), Some(h)) if mode == FitMode::Stretch => (u64::from(w), u64::from(h)),
+        (w, h) => fit(
+            u64::from(w.unwrap_or(img_width as u32)),
+            u64::from(h.unwrap_or(img_height as u32)),
+        ),
+    };
+    (std::cmp::max(1, w) as u32, std::cmp::max(1, h) as u32)
+}
+
+// Fit the image in bounds given in cells, for printers which display cell_pixels (columns,
+// rows) of the image in a single terminal cell. Returns the size in cells.
+fn fit_in_cells(
+    (img_width, img_height): (u32, u32),
+    width: Option<u32>,
+    height: Option<u32>,
+    cell_pixels: (u32, u32),
+    cell_size: (u32, u32),
+    mode: FitMode,
+) -> (u32, u32) {
+    let density = cell_pixels.0;
+
+    if !matches!(mode, FitMode::Stretch | FitMode::ScaleDown) {
+        // without scaling, a pixel column takes up 1/density of a cell, and square pixels are
+        // as tall as they are wide
+        let natural = (
+            f64::from(img_width) / f64::from(density),
+            f64::from(img_height) * f64::from(cell_size.0)
+                / (f64::from(cell_size.1) * f64::from(density)),
+        );
+        let bounds = match (width, height) {
+            (None, None) => {
+                let (columns, rows) = terminal_box();
+                (Some(columns), Some(rows))
+            }
+            bounds => bounds,
+        };
+        return scale_to_box(natural, bounds, mode);
+    }
+
+    // fit_dimensions works with cells holding a single pixel column, so the bounds are scaled
+    // up by the density and the result is scaled back down
+    let fit = |bound_width: u32, bound_height: u32| {
+        let (w, h) = fit_dimensions(img_width, img_height, bound_width, bound_height, cell_size);
+        (std::cmp::max(1, w / density), std::cmp::max(1, h / density))
+    };
 
     // Match user's width and height preferences
     match (width, height) {
         (None, None) => {
             let (term_w, term_h) = terminal_size();
-            let (w, h) = fit_dimensions(img_width, img_height, term_w as u32, term_h as u32);
+            let (w, h) = fit(density * term_w as u32, density * term_h as u32);
 
             // One less row because two reasons:
             // - the prompt after executing the command will take a line
@@ -78,57 +486,99 @@ fn find_best_fit(img: &DynamicImage, width: Option<u32>, height: Option<u32>) ->
             (w, h)
         }
         // Either width or height is specified, will fit and preserve aspect ratio.
-        (Some(w), None) => fit_dimensions(img_width, img_height, w, img_height),
-        (None, Some(h)) => fit_dimensions(img_width, img_height, img_width, h),
+        (Some(w), None) => fit(density * w, img_height),
+        (None, Some(h)) => fit(img_width, density * h),
+
+        // Both width and height are specified, will resize to match exactly, unless the
+        // image should only be scaled down
+        (Some(w), Some(h)) if mode == FitMode::Stretch => (w, h),
+        (Some(w), Some(h)) => fit(density * w, density * h),
+    }
+}
+
+// Scale the natural size of an image to a box for the Contain, Cover, Fill and Integer modes.
+// A missing bound does not limit the size. Cover and Fill take up the whole box if it is known,
+// as the image was already cropped or padded to its shape by fill_box.
+fn scale_to_box(
+    (natural_width, natural_height): (f64, f64),
+    bounds: (Option<u32>, Option<u32>),
+    mode: FitMode,
+) -> (u32, u32) {
+    if let (Some(w), Some(h), FitMode::Cover | FitMode::Fill) = (bounds.0, bounds.1, mode) {
+        return (w, h);
+    }
+
+    let scale_width = bounds.0.map(|w| f64::from(w) / natural_width);
+    let scale_height = bounds.1.map(|h| f64::from(h) / natural_height);
+    let mut scale = match (scale_width, scale_height) {
+        (Some(sw), Some(sh)) => sw.min(sh),
+        (Some(s), None) | (None, Some(s)) => s,
+        (None, None) => 1.0,
+    };
 
-        // Both width and height are specified, will resize to match exactly
-        (Some(w), Some(h)) => (w, h),
+    if mode == FitMode::Integer {
+        scale = if scale >= 1.0 {
+            scale.floor()
+        } else {
+            1.0 / (1.0 / scale).ceil()
+        };
     }
+
+    // a small tolerance, so that sizes which are whole numbers are not rounded down
+    let size = |natural: f64| std::cmp::max(1, (natural * scale + 1e-6).floor() as u32);
+    (size(natural_width), size(natural_height))
 }
 
 /// Given width & height of an image, scale the size so that it can fit within given bounds
 /// while preserving aspect ratio. Will only scale down - if dimensions are smaller than the
 /// bounds, they will be returned unmodified.
 ///
-/// Note: input bounds are meant to hold dimensions of a terminal, where the height of a cell is
-/// twice it's width. It is best illustrated in an example:
+/// Note: input bounds are meant to hold dimensions of a terminal, where a pixel of the image is
+/// as wide as a cell, and the height of a cell is given by the cell size (width, height). It is
+/// best illustrated in an example, with cells twice as tall as they are wide:
 ///
 /// Trying to fit a 100x100 image in 40x15 terminal cells. The best fit, while having an aspect
 /// ratio of 1:1, would be to use all of the available height, 15, which is
 /// equivalent in size to 30 vertical cells. Hence, the returned dimensions will be 30x15.
 ///
-/// assert_eq!((30, 15), viuer::fit_dimensions(100, 100, 40, 15));
-fn fit_dimensions(width: u32, height: u32, bound_width: u32, bound_height: u32) -> (u32, u32) {
-    let bound_height = 2 * bound_height;
+/// assert_eq!((30, 15), viuer::fit_dimensions(100, 100, 40, 15, (1, 2)));
+fn fit_dimensions(
+    width: u32,
+    height: u32,
+    bound_width: u32,
+    bound_height: u32,
+    (cell_width, cell_height): (u32, u32),
+) -> (u32, u32) {
+    // heights are compared in pixels, which are 1/cell_width of a cell wide, to stay in integers
+    let (width, height) = (u64::from(width), u64::from(height));
+    let (bound_width, bound_height) = (u64::from(bound_width), u64::from(bound_height));
+    let (cell_width, cell_height) = (u64::from(cell_width), u64::from(cell_height));
 
-    if width <= bound_width && height <= bound_height {
-        return (width, std::cmp::max(1, height / 2));
+    if width <= bound_width && height * cell_width <= bound_height * cell_height {
+        let h = height * cell_width / cell_height;
+        return (width as u32, std::cmp::max(1, h) as u32);
     }
 
-    let ratio = width * bound_height;
-    let nratio = bound_width * height;
+    let ratio = width * bound_height * cell_height;
+    let nratio = bound_width * height * cell_width;
 
     let use_width = nratio <= ratio;
-    let intermediate = if use_width {
-        height * bound_width / width
-    } else {
-        width * bound_height / height
-    };
-
     if use_width {
-        (bound_width, std::cmp::max(1, intermediate / 2))
+        let h = height * bound_width * cell_width / (width * cell_height);
+        (bound_width as u32, std::cmp::max(1, h) as u32)
     } else {
-        (intermediate, std::cmp::max(1, bound_height / 2))
+        let w = width * bound_height * cell_height / (height * cell_width);
+        (w as u32, std::cmp::max(1, bound_height) as u32)
     }
 }
 
 // Move the cursor to a location from where it should start printing. Calculations are based on
 // offsets from the config.
-fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
+fn adjust_offset(stdout: &mut dyn Write, config: &Config) -> ViuResult {
     if config.absolute_offset {
         if config.y >= 0 {
             // If absolute_offset, move to (x,y).
-            execute!(stdout, MoveTo(config.x, config.y as u16))?;
+            stdout.execute(MoveTo(config.x, config.y as u16))?;
         } else {
             //Negative values do not make sense.
             return Err(ViuError::InvalidConfiguration(
@@ -137,8 +587,8 @@ fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
         }
     } else if config.y < 0 {
         // MoveUp if negative
-        execute!(stdout, MoveToPreviousLine(-config.y as u16))?;
-        execute!(stdout, MoveRight(config.x))?;
+        stdout.execute(MoveToPreviousLine(-config.y as u16))?;
+        stdout.execute(MoveRight(config.x))?;
     } else {
         // Move down y lines
         for _ in 0..config.y {
@@ -146,7 +596,7 @@ fn adjust_offset(stdout: &mut impl Write, config: &Config) -> ViuResult {
             // observed when config.y > 0 and cursor is on the last terminal line
             writeln!(stdout)?;
         }
-        execute!(stdout, MoveRight(config.x))?;
+        stdout.execute(MoveRight(config.x))?;
     }
     Ok(())
 }
@@ -157,10 +607,23 @@ mod tests {
 
     fn test_adjust_offset_output(config: &Config, str: &str) {
         let mut vec = Vec::new();
-        adjust_offset(&mut vec, &config).unwrap();
+        adjust_offset(&mut vec, config).unwrap();
         assert_eq!(std::str::from_utf8(&vec).unwrap(), str);
     }
 
+    fn bounds(width: Option<u32>, height: Option<u32>) -> Config {
+        Config {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    // Size of a placement in cells, which is what most tests check
+    fn cells_of(placement: Placement) -> (u32, u32) {
+        (placement.columns, placement.rows)
+    }
+
     fn best_fit_large_test_image() -> DynamicImage {
         DynamicImage::ImageRgba8(image::RgbaImage::new(600, 500))
     }
@@ -252,17 +715,17 @@ mod tests {
         let height = None;
 
         let img = best_fit_large_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 57);
         assert_eq!(h, 23);
 
         let img = best_fit_small_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 40);
         assert_eq!(h, 12);
 
         let img = DynamicImage::ImageRgba8(image::RgbaImage::new(160, 80));
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 80);
         assert_eq!(h, 20);
     }
@@ -273,22 +736,22 @@ mod tests {
         let height = None;
 
         let img = best_fit_large_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 100);
         assert_eq!(h, 41);
 
         let img = best_fit_small_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 40);
         assert_eq!(h, 12);
 
         let width = Some(6);
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 6);
         assert_eq!(h, 1);
 
         let width = Some(3);
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 3);
         assert_eq!(h, 1);
     }
@@ -299,13 +762,13 @@ mod tests {
         let height = Some(90);
 
         let img = best_fit_large_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 216);
         assert_eq!(h, 90);
 
         let height = Some(4);
         let img = best_fit_small_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 12);
         assert_eq!(h, 4);
     }
@@ -316,36 +779,249 @@ mod tests {
         let height = Some(9);
 
         let img = best_fit_large_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 15);
         assert_eq!(h, 9);
 
         let img = best_fit_small_test_image();
-        let (w, h) = find_best_fit(&img, width, height);
+        let (w, h) = cells_of(find_best_fit(&img, &bounds(width, height)));
         assert_eq!(w, 15);
         assert_eq!(h, 9);
     }
 
+    #[test]
+    fn test_resize_for_cells() {
+        let img = resize_get_large_test_image();
+        let new_img = resize_for_cells(&img, &bounds(Some(100), None), (2, 2));
+        assert_eq!(new_img.width(), 200);
+        assert_eq!(new_img.height(), 80);
+
+        // small images take up fewer cells instead of being scaled up
+        let img = resize_get_small_test_image();
+        let new_img = resize_for_cells(&img, &bounds(None, None), (2, 2));
+        assert_eq!(new_img.width(), 20);
+        assert_eq!(new_img.height(), 4);
+    }
+
+    #[test]
+    fn find_best_fit_for_cells_quarter() {
+        let img = best_fit_large_test_image();
+        let (w, h) = cells_of(find_best_fit_for_cells(&img, &bounds(None, None), (2, 2)));
+        assert_eq!(w, 57);
+        assert_eq!(h, 23);
+
+        let img = best_fit_small_test_image();
+        let (w, h) = cells_of(find_best_fit_for_cells(&img, &bounds(None, None), (2, 2)));
+        assert_eq!(w, 20);
+        assert_eq!(h, 6);
+
+        let (w, h) = cells_of(find_best_fit_for_cells(
+            &img,
+            &bounds(None, Some(4)),
+            (2, 2),
+        ));
+        assert_eq!(w, 12);
+        assert_eq!(h, 4);
+    }
+
+    #[test]
+    fn find_best_fit_for_cells_sextant_octant() {
+        let img = best_fit_small_test_image();
+        // only the amount of pixel columns in a cell changes the fit
+        for cell_pixels in [(2, 3), (2, 4)].iter() {
+            let (w, h) = cells_of(find_best_fit_for_cells(
+                &img,
+                &bounds(None, None),
+                *cell_pixels,
+            ));
+            assert_eq!(w, 20);
+            assert_eq!(h, 6);
+
+            let (w, h) = cells_of(find_best_fit_for_cells(
+                &img,
+                &bounds(Some(10), None),
+                *cell_pixels,
+            ));
+            assert_eq!(w, 10);
+            assert_eq!(h, 3);
+        }
+
+        let new_img = resize_for_cells(&img, &bounds(None, None), (2, 3));
+        assert_eq!((new_img.width(), new_img.height()), (40, 18));
+        let new_img = resize_for_cells(&img, &bounds(None, None), (2, 4));
+        assert_eq!((new_img.width(), new_img.height()), (40, 24));
+    }
+
     #[test]
     fn test_fit_dimensions() {
         // ratio 1:1
-        assert_eq!((40, 20), fit_dimensions(100, 100, 40, 50));
-        assert_eq!((20, 10), fit_dimensions(100, 100, 40, 10));
+        assert_eq!((40, 20), fit_dimensions(100, 100, 40, 50, (1, 2)));
+        assert_eq!((20, 10), fit_dimensions(100, 100, 40, 10, (1, 2)));
         // ratio 3:2
-        assert_eq!((30, 10), fit_dimensions(240, 160, 30, 100));
+        assert_eq!((30, 10), fit_dimensions(240, 160, 30, 100, (1, 2)));
         // ratio 5:7
-        assert_eq!((200, 140), fit_dimensions(300, 420, 320, 140));
+        assert_eq!((200, 140), fit_dimensions(300, 420, 320, 140, (1, 2)));
+    }
+
+    #[test]
+    fn test_fit_dimensions_cell_size() {
+        // square cells show as many rows as columns for a square image
+        assert_eq!((40, 40), fit_dimensions(100, 100, 40, 50, (10, 10)));
+        // 8x18 cells are a bit more than twice as tall as they are wide
+        assert_eq!((40, 17), fit_dimensions(100, 100, 40, 50, (8, 18)));
+        assert_eq!((22, 10), fit_dimensions(100, 100, 40, 10, (8, 18)));
+    }
+
+    #[test]
+    fn test_cell_size_override() {
+        let config = Config {
+            cell_aspect_ratio: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(cell_size(&config), (10, 10));
+        assert_eq!(cell_size(&Config::default()), (10, 20));
+
+        let img = best_fit_large_test_image();
+        let config = Config {
+            width: Some(60),
+            ..config
+        };
+        assert_eq!(cells_of(find_best_fit(&img, &config)), (60, 50));
+    }
+
+    #[test]
+    fn find_best_fit_units() {
+        let img = best_fit_large_test_image();
+
+        let config = Config {
+            width: Some(300),
+            size_unit: SizeUnit::Pixels,
+            ..Default::default()
+        };
+        let expected = Placement {
+            columns: 30,
+            rows: 13,
+            width: 300,
+            height: 250,
+        };
+        assert_eq!(find_best_fit(&img, &config), expected);
+
+        // half of the 80x24 terminal
+        let config = Config {
+            width: Some(50),
+            size_unit: SizeUnit::Percent,
+            ..Default::default()
+        };
+        let expected = Placement {
+            columns: 40,
+            rows: 16,
+            width: 400,
+            height: 320,
+        };
+        assert_eq!(find_best_fit(&img, &config), expected);
+    }
+
+    #[test]
+    fn test_fit_modes() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 10));
+        let fit = |fit_mode, width, height| {
+            let config = Config {
+                width: Some(width),
+                height: Some(height),
+                fit_mode,
+                ..Default::default()
+            };
+            cells_of(find_best_fit(&img, &config))
+        };
+
+        assert_eq!(fit(FitMode::Stretch, 10, 10), (10, 10));
+        assert_eq!(fit(FitMode::ScaleDown, 10, 10), (10, 2));
+        assert_eq!(fit(FitMode::ScaleDown, 40, 40), (20, 5));
+        assert_eq!(fit(FitMode::Contain, 40, 40), (40, 10));
+        assert_eq!(fit(FitMode::Integer, 50, 50), (40, 10));
+        assert_eq!(fit(FitMode::Integer, 15, 15), (10, 2));
+        assert_eq!(fit(FitMode::Cover, 30, 7), (30, 7));
+        assert_eq!(fit(FitMode::Fill, 30, 7), (30, 7));
+
+        // without a box to fill, the image is only scaled
+        let config = Config {
+            width: Some(40),
+            fit_mode: FitMode::Cover,
+            ..Default::default()
+        };
+        assert_eq!(cells_of(find_best_fit(&img, &config)), (40, 10));
+    }
+
+    #[test]
+    fn test_fill_box() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::new(20, 10));
+        // 10x5 cells of 10x20 pixels are a square
+        let config = |fit_mode| Config {
+            width: Some(10),
+            height: Some(5),
+            fit_mode,
+            ..Default::default()
+        };
+
+        let cropped = fill_box(&img, &config(FitMode::Cover));
+        assert_eq!(cropped.dimensions(), (10, 10));
+        let padded = fill_box(&img, &config(FitMode::Fill));
+        assert_eq!(padded.dimensions(), (20, 20));
+        let unchanged = fill_box(&img, &config(FitMode::Contain));
+        assert!(matches!(unchanged, Cow::Borrowed(_)));
+
+        // empty boxes are a pixel wide or high
+        let empty = |width, height, fit_mode| Config {
+            width: Some(width),
+            height: Some(height),
+            fit_mode,
+            ..Default::default()
+        };
+        let cropped = fill_box(&img, &empty(0, 5, FitMode::Cover));
+        assert_eq!(cropped.dimensions(), (1, 10));
+        let cropped = fill_box(&img, &empty(10, 0, FitMode::Cover));
+        assert_eq!(cropped.dimensions(), (20, 1));
+        let padded = fill_box(&img, &empty(0, 5, FitMode::Fill));
+        assert_eq!(padded.dimensions(), (20, 2000));
+        find_best_fit(&img, &empty(0, 0, FitMode::Cover));
     }
 
     #[test]
     fn test_fit_smaller_than_bounds() {
-        assert_eq!((4, 1), fit_dimensions(4, 3, 80, 24));
-        assert_eq!((4, 1), fit_dimensions(4, 1, 80, 24));
+        assert_eq!((4, 1), fit_dimensions(4, 3, 80, 24, (1, 2)));
+        assert_eq!((4, 1), fit_dimensions(4, 1, 80, 24, (1, 2)));
     }
 
     #[test]
     fn test_fit_equal_to_bounds() {
-        assert_eq!((80, 12), fit_dimensions(80, 24, 80, 24));
+        assert_eq!((80, 12), fit_dimensions(80, 24, 80, 24, (1, 2)));
+    }
+
+    #[test]
+    fn test_align() {
+        let config = Config {
+            x: 1,
+            width: Some(40),
+            height: Some(10),
+            align_x: Align::Center,
+            align_y: Align::Center,
+            ..Default::default()
+        };
+        let aligned = align(&config, (20, 4));
+        assert_eq!((aligned.x, aligned.y), (11, 3));
+
+        // without bounds, the 80x24 terminal is used, minus a row for the prompt
+        let config = Config {
+            align_x: Align::End,
+            align_y: Align::End,
+            ..Default::default()
+        };
+        let aligned = align(&config, (20, 4));
+        assert_eq!((aligned.x, aligned.y), (60, 19));
+
+        let config = Config::default();
+        let aligned = align(&config, (20, 4));
+        assert!(matches!(aligned, Cow::Borrowed(_)));
     }
 
     #[test]
@@ -0,0 +1,172 @@
+use super::MIN_DRAWN_ALPHA;
+use crate::color::closest_in_palette;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Sixels are written as this character plus the bits of the six pixels they draw
+const SIXEL_BASE: u8 = 0x3f;
+
+// Runs of the same sixel which are at least this long are compressed
+const MIN_RUN: usize = 4;
+
+// Encode an image as a Sixel sequence with a palette of at most 256 colors. Every pixel gets the
+// closest color of the palette, and mostly transparent pixels are not drawn. If `transparent` is
+// set, the terminal keeps what was under those pixels, otherwise it uses its background color.
+pub(super) fn encode(img: &RgbaImage, palette: &[[u8; 3]], transparent: bool) -> String {
+    let (width, height) = img.dimensions();
+    let palette = &palette[..palette.len().min(256)];
+    let indices = palette_indices(img, palette);
+
+    // P2=1 leaves undrawn pixels as they are, P2=0 sets them to the background color. The
+    // raster attributes give pixels an aspect ratio of 1:1 and the size of the image.
+    let mut out = String::new();
+    let p2 = if transparent { 1 } else { 0 };
+    let _ = write!(out, "\x1bP0;{};0q\"1;1;{};{}", p2, width, height);
+
+    // the palette is defined with percentages of RGB
+    for (i, color) in palette.iter().enumerate() {
+        let percent = |c: u8| (u32::from(c) * 100 + 127) / 255;
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            percent(color[0]),
+            percent(color[1]),
+            percent(color[2])
+        );
+    }
+
+    let width = width as usize;
+    let mut band = vec![0u8; width * palette.len()];
+    let mut used = vec![false; palette.len()];
+
+    // every band draws six rows of pixels, one color after the other
+    for band_top in (0..height as usize).step_by(6) {
+        band.iter_mut().for_each(|sixel| *sixel = 0);
+        used.iter_mut().for_each(|u| *u = false);
+
+        for dy in 0..6.min(height as usize - band_top) {
+            let row = &indices[(band_top + dy) * width..(band_top + dy + 1) * width];
+            for (x, index) in row.iter().enumerate() {
+                if let Some(index) = index {
+                    let index = usize::from(*index);
+                    band[index * width + x] |= 1 << dy;
+                    used[index] = true;
+                }
+            }
+        }
+
+        let mut first = true;
+        for (index, sixels) in band.chunks(width).enumerate() {
+            if !used[index] {
+                continue;
+            }
+            // return to the start of the band before drawing the next color
+            if !first {
+                out.push('$');
+            }
+            first = false;
+
+            let _ = write!(out, "#{}", index);
+            write_sixels(&mut out, sixels);
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+// Write a row of sixels, compressing runs of the same one. Empty sixels at the end of the row
+// are left out, as they don't draw anything.
+fn write_sixels(out: &mut String, sixels: &[u8]) {
+    let end = sixels.iter().rposition(|&s| s != 0).map_or(0, |i| i + 1);
+    let mut sixels = sixels[..end].iter().peekable();
+
+    while let Some(&sixel) = sixels.next() {
+        let mut run = 1;
+        while sixels.next_if_eq(&&sixel).is_some() {
+            run += 1;
+        }
+
+        let c = char::from(SIXEL_BASE + sixel);
+        if run >= MIN_RUN {
+            let _ = write!(out, "!{}{}", run, c);
+        } else {
+            (0..run).for_each(|_| out.push(c));
+        }
+    }
+}
+
+// Index of the closest palette color for every pixel, row by row. Mostly transparent pixels have
+// no color.
+fn palette_indices(img: &RgbaImage, palette: &[[u8; 3]]) -> Vec<Option<u8>> {
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    img.pixels()
+        .map(|pixel| {
+            if pixel[3] < MIN_DRAWN_ALPHA {
+                return None;
+            }
+            let color = [pixel[0], pixel[1], pixel[2]];
+            let index = *cache
+                .entry(color)
+                .or_insert_with(|| closest_in_palette(color, palette) as u8);
+            Some(index)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_encode() {
+        let mut img = RgbaImage::from_pixel(5, 7, Rgba([255, 0, 0, 255]));
+        img.put_pixel(4, 0, Rgba([0, 0, 255, 255]));
+        img.put_pixel(0, 6, Rgba([0, 0, 0, 0]));
+
+        let encoded = encode(&img, &[[0, 0, 255], [255, 0, 0]], false);
+        assert_eq!(
+            encoded,
+            concat!(
+                "\x1bP0;0;0q\"1;1;5;7",
+                "#0;2;0;0;100#1;2;100;0;0",
+                // the blue pixel in the first band, then the red ones around it
+                "#0!4?@$#1!4~}-",
+                // the second band has a single row, starting with the transparent pixel
+                "#1?!4@-",
+                "\x1b\\"
+            )
+        );
+    }
+
+    #[test]
+    fn test_encode_transparent() {
+        let img = RgbaImage::new(2, 2);
+        assert_eq!(encode(&img, &[], true), "\x1bP0;1;0q\"1;1;2;2-\x1b\\");
+    }
+
+    #[test]
+    fn test_encode_semi_transparent() {
+        let mut img = RgbaImage::from_pixel(3, 1, Rgba([255, 0, 0, 255]));
+        img.put_pixel(0, 0, Rgba([0, 0, 255, 127]));
+        img.put_pixel(2, 0, Rgba([0, 0, 255, 128]));
+
+        // the pixel with less than half alpha is left out, the other one is drawn opaque
+        let encoded = encode(&img, &[[0, 0, 255], [255, 0, 0]], true);
+        assert_eq!(
+            encoded,
+            "\x1bP0;1;0q\"1;1;3;1#0;2;0;0;100#1;2;100;0;0#0??@$#1?@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_write_sixels() {
+        let mut out = String::new();
+        write_sixels(&mut out, &[1, 1, 1, 1, 1, 2, 2, 0, 0]);
+        assert_eq!(out, "!5@AA");
+    }
+}
@@ -0,0 +1,288 @@
+use crate::color::dither_to_palette;
+use crate::error::ViuResult;
+use crate::printer::block::get_transparency_rgb;
+use crate::printer::{adjust_offset, align, find_best_fit, Printer};
+use crate::utils::{self, write_passthrough};
+use crate::Config;
+use image::{DynamicImage, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+#[cfg(feature = "sixel")]
+use sixel_rs::encoder::{Encoder, QuickFrameBuilder};
+#[cfg(feature = "sixel")]
+use sixel_rs::optflags::{DiffusionMethod, EncodePolicy};
+use std::io::Write;
+
+mod encoder;
+mod quantize;
+
+pub use self::quantize::Quantizer;
+
+// Amount of color registers used if the terminal does not report it. Most terminals have at
+// least 256, which is also the most the encoder uses.
+const DEFAULT_COLOR_REGISTERS: usize = 256;
+
+// Pixels of transparent images with less alpha than this are not drawn. Sixel has no partial
+// transparency, so anti-aliased edges are either drawn opaque or left out.
+const MIN_DRAWN_ALPHA: u8 = 128;
+
+pub struct SixelPrinter {}
+
+lazy_static! {
+    static ref SIXEL_SUPPORT: SixelSupport = check_sixel_support();
+    static ref COLOR_REGISTERS: usize = query_color_registers().unwrap_or(DEFAULT_COLOR_REGISTERS);
+    static ref MAX_GEOMETRY: Option<(u32, u32)> = query_max_geometry();
+}
+
+// How Sixel images reach the terminal. Inside a multiplexer, they are either displayed by the
+// multiplexer itself, or passed through to the terminal outside.
+#[derive(PartialEq, Eq, Copy, Clone)]
+enum SixelSupport {
+    None,
+    Direct,
+    Passthrough,
+}
+
+/// Returns the terminal's support for Sixel.
+pub fn is_sixel_supported() -> bool {
+    *SIXEL_SUPPORT != SixelSupport::None
+}
+
+impl Printer for SixelPrinter {
+    fn print(
+        &self,
+        stdout: &mut dyn Write,
+        img: &DynamicImage,
+        config: &Config,
+    ) -> ViuResult<(u32, u32)> {
+        let placement = find_best_fit(img, config);
+
+        // terminals which limit the size of Sixel images get smaller ones, taking up fewer cells
+        let (width, height) = limit_size((placement.width, placement.height), *MAX_GEOMETRY);
+        let resized_img = img.resize_exact(width, height, super::filter_type(config));
+        let (cell_width, cell_height) = super::cell_size(config);
+        let size = (
+            std::cmp::max(1, width.div_ceil(cell_width)),
+            std::cmp::max(1, height.div_ceil(cell_height)),
+        );
+
+        // the colors are reduced to the terminal's registers here, so that both encoders get
+        // the same palette
+        let mut rgba = resized_img.to_rgba8();
+        if !config.transparent {
+            composite_on_checkerboard(&mut rgba, super::cell_size(config));
+        }
+        let palette = quantize::palette(&rgba, *COLOR_REGISTERS, config.sixel_quantizer);
+        let rgba = dither_to_palette(&rgba, &palette, config.sixel_dither, MIN_DRAWN_ALPHA);
+        let sixel_data = encode(&rgba, &palette, config.transparent)?;
+
+        adjust_offset(stdout, &align(config, size))?;
+
+        if *SIXEL_SUPPORT == SixelSupport::Passthrough {
+            write_passthrough(stdout, &sixel_data)?;
+        } else {
+            stdout.write_all(sixel_data.as_bytes())?;
+        }
+        stdout.flush()?;
+
+        Ok(size)
+    }
+}
+
+// Encode the image with libsixel. It only has the colors of the palette already, which libsixel
+// keeps as they are. libsixel can't leave pixels transparent, so those images are encoded
+// natively.
+#[cfg(feature = "sixel")]
+fn encode(img: &RgbaImage, palette: &[[u8; 3]], transparent: bool) -> ViuResult<String> {
+    if transparent {
+        return Ok(encoder::encode(img, palette, true));
+    }
+    let (width, height) = img.dimensions();
+
+    // libsixel can only write to a file, so the output goes through a temp file
+    // which is then copied into the writer
+    let tmpfile = tempfile::NamedTempFile::new()?;
+
+    let encoder = Encoder::new()?;
+
+    encoder.set_encode_policy(EncodePolicy::Fast)?;
+    encoder.set_num_colors_str(&palette.len().max(2).to_string())?;
+    encoder.set_diffusion(DiffusionMethod::None)?;
+    encoder.set_output(tmpfile.path())?;
+
+    let frame = QuickFrameBuilder::new()
+        .width(width as usize)
+        .height(height as usize)
+        .format(sixel_sys::PixelFormat::RGBA8888)
+        .pixels(img.as_raw().to_vec());
+
+    encoder.encode_bytes(frame)?;
+
+    Ok(std::fs::read_to_string(tmpfile.path())?)
+}
+
+// Encode the image with the native encoder, when libsixel is not enabled
+#[cfg(not(feature = "sixel"))]
+fn encode(img: &RgbaImage, palette: &[[u8; 3]], transparent: bool) -> ViuResult<String> {
+    Ok(encoder::encode(img, palette, transparent))
+}
+
+// Blend the pixels which are not opaque with the checkerboard drawn by the block printer, whose
+// squares are as wide as a cell and half as tall
+fn composite_on_checkerboard(img: &mut RgbaImage, (cell_width, cell_height): (u32, u32)) {
+    let (square_width, square_height) = (cell_width.max(1), (cell_height / 2).max(1));
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let alpha = u32::from(pixel[3]);
+        if alpha == 255 {
+            continue;
+        }
+
+        let background = get_transparency_rgb(y / square_height, x / square_width);
+        let blend =
+            |c: u8, bg: u8| ((u32::from(c) * alpha + u32::from(bg) * (255 - alpha)) / 255) as u8;
+        *pixel = Rgba([
+            blend(pixel[0], background.0),
+            blend(pixel[1], background.1),
+            blend(pixel[2], background.2),
+            255,
+        ]);
+    }
+}
+
+// Ask the terminal how many color registers it has, with XTSMGRAPHICS
+// see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
+fn query_color_registers() -> Option<usize> {
+    let response = utils::query_terminal("\x1b[?1;1S")?;
+    match parse_graphics_attribute(&response, 1)?.as_slice() {
+        &[registers] => Some((registers as usize).clamp(2, DEFAULT_COLOR_REGISTERS)),
+        _ => None,
+    }
+}
+
+// Ask the terminal for the largest Sixel image it displays, with XTSMGRAPHICS. Terminals which
+// don't answer are assumed to have no limit.
+fn query_max_geometry() -> Option<(u32, u32)> {
+    let response = utils::query_terminal("\x1b[?2;1S")?;
+    match parse_graphics_attribute(&response, 2)?.as_slice() {
+        &[width, height] if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+// Scale a size down to fit in the limit, if there is one, keeping its aspect ratio
+fn limit_size((width, height): (u32, u32), limit: Option<(u32, u32)>) -> (u32, u32) {
+    let (max_width, max_height) = match limit {
+        Some(limit) if width > limit.0 || height > limit.1 => limit,
+        _ => return (width, height),
+    };
+
+    let (w, h) = (u64::from(width), u64::from(height));
+    let (max_w, max_h) = (u64::from(max_width), u64::from(max_height));
+    let (w, h) = if max_w * h <= max_h * w {
+        (max_w, h * max_w / w)
+    } else {
+        (w * max_h / h, max_h)
+    };
+    (std::cmp::max(1, w) as u32, std::cmp::max(1, h) as u32)
+}
+
+// Parse the reply to an XTSMGRAPHICS query, `CSI ? item ; status ; values S`. The values are
+// returned if the status is 0, which means success.
+fn parse_graphics_attribute(response: &str, item: u8) -> Option<Vec<u32>> {
+    let prefix = format!("\x1b[?{};", item);
+    let start = response.find(&prefix)? + prefix.len();
+    let end = start + response[start..].find('S')?;
+
+    let mut fields = response[start..end].split(';');
+    if fields.next()? != "0" {
+        return None;
+    }
+    fields.map(|value| value.parse().ok()).collect()
+}
+
+// Check if Sixel is within the terminal's attributes
+// see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Sixel-Graphics
+// and https://vt100.net/docs/vt510-rm/DA1.html
+fn check_device_attrs() -> bool {
+    utils::device_attributes()
+        .is_some_and(|response| response.contains(";4;") || response.contains(";4c"))
+}
+
+// Check if Sixel protocol can be used
+fn check_sixel_support() -> SixelSupport {
+    // inside a multiplexer, this is the terminal outside of it
+    if let Some(term) = utils::terminal_name() {
+        match term.as_str() {
+            "mlterm" | "yaft-256color" => return SixelSupport::Passthrough,
+            // multiplexers answer the device attributes themselves, so if Sixel is listed
+            // there, they display the images on their own
+            "st-256color" | "xterm" | "xterm-256color" => {
+                if check_device_attrs() {
+                    return SixelSupport::Direct;
+                }
+            }
+            _ => {
+                if std::env::var("TERM_PROGRAM").is_ok_and(|program| program == "MacTerm") {
+                    return SixelSupport::Passthrough;
+                }
+            }
+        }
+    }
+    SixelSupport::None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dither;
+
+    #[test]
+    fn test_composite_on_checkerboard() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        img.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        img.put_pixel(3, 3, Rgba([255, 255, 255, 51]));
+        composite_on_checkerboard(&mut img, (2, 4));
+
+        assert_eq!(img.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        // squares of 2x2 pixels
+        assert_eq!(img.get_pixel(1, 1), &Rgba([102, 102, 102, 255]));
+        assert_eq!(img.get_pixel(2, 0), &Rgba([153, 153, 153, 255]));
+        assert_eq!(img.get_pixel(3, 3), &Rgba([132, 132, 132, 255]));
+    }
+
+    #[test]
+    fn test_encode_undrawn_image() {
+        // a faint shadow has no pixels which are drawn, so there are no colors to pick
+        let img = RgbaImage::from_pixel(4, 4, Rgba([200, 10, 10, 60]));
+        let palette = quantize::palette(&img, 256, Quantizer::MedianCut);
+        assert!(palette.is_empty());
+
+        let dithered = dither_to_palette(&img, &palette, Dither::FloydSteinberg, MIN_DRAWN_ALPHA);
+        assert_eq!(dithered, img);
+        assert_eq!(
+            encoder::encode(&dithered, &palette, true),
+            "\x1bP0;1;0q\"1;1;4;4-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_limit_size() {
+        assert_eq!(limit_size((2000, 500), None), (2000, 500));
+        assert_eq!(limit_size((800, 500), Some((1000, 1000))), (800, 500));
+        assert_eq!(limit_size((2000, 500), Some((1000, 1000))), (1000, 250));
+        assert_eq!(limit_size((500, 3000), Some((1000, 1000))), (166, 1000));
+    }
+
+    #[test]
+    fn test_parse_graphics_attribute() {
+        let response = "\x1b[?1;0;256S";
+        assert_eq!(parse_graphics_attribute(response, 1), Some(vec![256]));
+        assert_eq!(parse_graphics_attribute(response, 2), None);
+        assert_eq!(
+            parse_graphics_attribute("\x1b[?2;0;1000;600S", 2),
+            Some(vec![1000, 600])
+        );
+        // failed queries
+        assert_eq!(parse_graphics_attribute("\x1b[?1;3;0S", 1), None);
+        assert_eq!(parse_graphics_attribute("\x1b[?1;0;abcS", 1), None);
+    }
+}
@@ -0,0 +1,300 @@
+use super::MIN_DRAWN_ALPHA;
+use crate::color::closest_in_palette;
+use image::RgbaImage;
+use std::collections::HashMap;
+
+// Depth of the octree. Colors in leaves at this depth only differ in bits which are dropped.
+const OCTREE_DEPTH: usize = 8;
+
+// Most rounds of k-means refinement, which usually settles before that
+const K_MEANS_ITERATIONS: usize = 8;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+/// Algorithm used to pick the colors of a Sixel image, when it has more colors than the
+/// terminal has color registers.
+pub enum Quantizer {
+    /// Split the colors into boxes at the median of their widest channel. Fast and balanced.
+    MedianCut,
+    /// Merge similar colors in an octree, starting with the least used ones. Keeps small
+    /// areas of distinct colors.
+    Octree,
+    /// Refine the median cut palette with k-means clustering. Slowest, but gives the colors
+    /// closest to the image.
+    KMeans,
+}
+
+// Pick at most max_colors colors for the pixels of the image which are drawn. If there are few
+// enough colors, all of them are used as they are.
+pub(super) fn palette(img: &RgbaImage, max_colors: usize, quantizer: Quantizer) -> Vec<[u8; 3]> {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in img.pixels().filter(|p| p[3] >= MIN_DRAWN_ALPHA) {
+        *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+    }
+
+    let mut colors: Vec<([u8; 3], u32)> = histogram.into_iter().collect();
+    if colors.len() <= max_colors {
+        colors.sort_unstable();
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    match quantizer {
+        Quantizer::MedianCut => median_cut(colors, max_colors),
+        Quantizer::Octree => octree(&colors, max_colors),
+        Quantizer::KMeans => k_means(&colors, max_colors),
+    }
+}
+
+// The color space is split into boxes at the median of their widest channel, until there are
+// enough boxes. Each box becomes the average of its colors.
+fn median_cut(colors: Vec<([u8; 3], u32)>, max_colors: usize) -> Vec<[u8; 3]> {
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        // split the box with the widest range of a channel
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range);
+        let (i, channel) = match widest {
+            Some((i, (channel, _))) => (i, channel),
+            None => break,
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+
+        // the median is weighted by how often the colors are used
+        let total: u64 = colors.iter().map(|&(_, count)| u64::from(count)).sum();
+        let mut seen = 0;
+        let median = colors
+            .iter()
+            .position(|&(_, count)| {
+                seen += u64::from(count);
+                2 * seen >= total
+            })
+            .unwrap_or(0);
+        let other = colors.split_off((median + 1).min(colors.len() - 1));
+        boxes.push(colors);
+        boxes.push(other);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+// Channel with the largest range of values in the colors, and that range
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+// Average of colors, weighted by how often they are used
+fn average(colors: &[([u8; 3], u32)]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let mut total = 0u64;
+    for &(color, count) in colors {
+        for (s, c) in sum.iter_mut().zip(color.iter()) {
+            *s += u64::from(*c) * u64::from(count);
+        }
+        total += u64::from(count);
+    }
+    mean(sum, total)
+}
+
+// Round the sum of colors divided by their amount
+fn mean(sum: [u64; 3], count: u64) -> [u8; 3] {
+    let count = count.max(1);
+    [
+        ((sum[0] + count / 2) / count) as u8,
+        ((sum[1] + count / 2) / count) as u8,
+        ((sum[2] + count / 2) / count) as u8,
+    ]
+}
+
+#[derive(Default)]
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [u64; 3],
+    count: u64,
+}
+
+// Every color is put in a leaf of an octree, following the bits of its channels from the most
+// significant one. Then the deepest nodes are merged into their parents, starting with the
+// least used ones, until there are few enough leaves.
+fn octree(colors: &[([u8; 3], u32)], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut nodes = vec![OctreeNode::default()];
+    // the nodes which have children, by their depth
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+    levels[0].push(0);
+    let mut leaves = 0;
+
+    for &(color, count) in colors {
+        let mut node = 0;
+        for depth in 0..OCTREE_DEPTH {
+            let bit = 7 - depth;
+            let child = (usize::from((color[0] >> bit) & 1) << 2)
+                | (usize::from((color[1] >> bit) & 1) << 1)
+                | usize::from((color[2] >> bit) & 1);
+
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    nodes.push(OctreeNode::default());
+                    let next = nodes.len() - 1;
+                    nodes[node].children[child] = Some(next);
+                    if depth + 1 < OCTREE_DEPTH {
+                        levels[depth + 1].push(next);
+                    } else {
+                        leaves += 1;
+                    }
+                    next
+                }
+            };
+        }
+
+        let leaf = &mut nodes[node];
+        for (s, c) in leaf.sum.iter_mut().zip(color.iter()) {
+            *s += u64::from(*c) * u64::from(count);
+        }
+        leaf.count += u64::from(count);
+    }
+
+    for depth in (0..OCTREE_DEPTH).rev() {
+        if leaves <= max_colors {
+            break;
+        }
+
+        // all the children of nodes at this depth are leaves by now, so their counts are final
+        let mut level = std::mem::take(&mut levels[depth]);
+        let subtree_count = |nodes: &[OctreeNode], node: usize| -> u64 {
+            nodes[node]
+                .children
+                .iter()
+                .flatten()
+                .map(|&c| nodes[c].count)
+                .sum()
+        };
+        level.sort_by_key(|&node| std::cmp::Reverse(subtree_count(&nodes, node)));
+
+        while leaves > max_colors {
+            let node = match level.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            let children = std::mem::take(&mut nodes[node].children);
+            for child in children.iter().flatten() {
+                let (sum, count) = (nodes[*child].sum, nodes[*child].count);
+                // the merged leaf is not a color of the palette anymore
+                nodes[*child].count = 0;
+                let parent = &mut nodes[node];
+                for (s, c) in parent.sum.iter_mut().zip(sum.iter()) {
+                    *s += c;
+                }
+                parent.count += count;
+                leaves -= 1;
+            }
+            leaves += 1;
+        }
+    }
+
+    nodes
+        .iter()
+        .filter(|node| node.count > 0)
+        .map(|node| mean(node.sum, node.count))
+        .collect()
+}
+
+// Start with the median cut palette, then repeatedly move every color of the palette to the
+// average of the image colors closest to it
+fn k_means(colors: &[([u8; 3], u32)], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut palette = median_cut(colors.to_vec(), max_colors);
+
+    for _ in 0..K_MEANS_ITERATIONS {
+        let mut sums = vec![[0u64; 3]; palette.len()];
+        let mut counts = vec![0u64; palette.len()];
+        for &(color, count) in colors {
+            let i = closest_in_palette(color, &palette);
+            for (s, c) in sums[i].iter_mut().zip(color.iter()) {
+                *s += u64::from(*c) * u64::from(count);
+            }
+            counts[i] += u64::from(count);
+        }
+
+        let mut changed = false;
+        for (i, color) in palette.iter_mut().enumerate() {
+            // colors which are not the closest to anything are kept
+            if counts[i] > 0 {
+                let new = mean(sums[i], counts[i]);
+                changed |= new != *color;
+                *color = new;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn gradient() -> RgbaImage {
+        let mut img = RgbaImage::new(16, 16);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            *pixel = Rgba([(x * 16) as u8, (y * 16) as u8, 0, 255]);
+        }
+        img
+    }
+
+    #[test]
+    fn test_palette_size() {
+        let img = gradient();
+        for &quantizer in &[Quantizer::MedianCut, Quantizer::Octree, Quantizer::KMeans] {
+            let reduced = palette(&img, 16, quantizer);
+            assert!(
+                !reduced.is_empty() && reduced.len() <= 16,
+                "{:?}",
+                quantizer
+            );
+            // few enough colors are kept as they are
+            assert_eq!(palette(&img, 256, quantizer).len(), 256);
+        }
+    }
+
+    #[test]
+    fn test_palette_skips_undrawn() {
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, Rgba([0, 0, 255, 100]));
+        assert_eq!(palette(&img, 256, Quantizer::MedianCut), vec![[255, 0, 0]]);
+    }
+
+    #[test]
+    fn test_octree_merges_least_used() {
+        let colors = [([0, 0, 0], 100), ([0, 0, 1], 1), ([255, 255, 255], 100)];
+        let palette = octree(&colors, 2);
+        assert_eq!(palette, vec![[0, 0, 0], [255, 255, 255]]);
+    }
+
+    #[test]
+    fn test_k_means() {
+        let colors = [
+            ([0, 0, 0], 10),
+            ([10, 0, 0], 10),
+            ([200, 0, 0], 10),
+            ([210, 0, 0], 10),
+        ];
+        let mut palette = k_means(&colors, 2);
+        palette.sort_unstable();
+        assert_eq!(palette, vec![[5, 0, 0], [205, 0, 0]]);
+    }
+}
@@ -1,12 +1,165 @@
+use crate::color::ColorDepth;
+use lazy_static::lazy_static;
+use std::convert::TryFrom;
 use std::env;
+use std::io::Write;
+use std::path::PathBuf;
 
 const DEFAULT_TERM_SIZE: (u16, u16) = (80, 24);
 
-pub fn truecolor_available() -> bool {
-    if let Ok(value) = env::var("COLORTERM") {
-        value.contains("truecolor") || value.contains("24bit")
+// GNU screen drops DCS strings longer than this, so passthrough sequences are split
+const SCREEN_CHUNK_SIZE: usize = 768;
+
+// How long to wait for the terminal to respond to a query, in milliseconds
+#[cfg(unix)]
+const QUERY_TIMEOUT: i32 = 500;
+
+#[cfg(not(test))]
+lazy_static! {
+    static ref CELL_SIZE: Option<(u16, u16)> = detect_cell_size();
+    static ref MULTIPLEXER: Multiplexer = detect_multiplexer();
+    static ref OUTER_TERMINAL: Option<String> = detect_outer_terminal();
+}
+
+// Terminal multiplexer the program runs in. Multiplexers don't understand graphics protocols,
+// so their escape sequences have to be passed through to the terminal outside.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub(crate) enum Multiplexer {
+    None,
+    Tmux,
+    Screen,
+}
+
+lazy_static! {
+    static ref COLOR_DEPTH: ColorDepth = detect_color_depth();
+}
+
+// Amount of colors the terminal supports, which is detected once as every Config::default()
+// needs it
+pub fn color_depth() -> ColorDepth {
+    *COLOR_DEPTH
+}
+
+// Detect the amount of colors the terminal supports. COLORTERM is checked for truecolor, while
+// the rest is taken from the terminfo entry of TERM. If it can't be found, 256 colors are assumed.
+fn detect_color_depth() -> ColorDepth {
+    color_depth_from(
+        env::var_os("NO_COLOR").is_some(),
+        env::var("COLORTERM").ok().as_deref(),
+        env::var("TERM").ok().as_deref(),
+        terminfo_colors,
+    )
+}
+
+// Pick the color depth from the environment, with the max_colors capability of a terminal given
+// by `colors`
+fn color_depth_from(
+    no_color: bool,
+    colorterm: Option<&str>,
+    term: Option<&str>,
+    colors: impl FnOnce(&str) -> Option<i32>,
+) -> ColorDepth {
+    // the user opted out of colors, see https://no-color.org
+    if no_color {
+        return ColorDepth::Mono;
+    }
+
+    if colorterm.is_some_and(|value| value.contains("truecolor") || value.contains("24bit")) {
+        return ColorDepth::Truecolor;
+    }
+
+    match term {
+        Some("dumb") => ColorDepth::Mono,
+        Some(term) => match colors(term) {
+            Some(colors) if colors >= 1 << 24 => ColorDepth::Truecolor,
+            Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+            Some(colors) if colors >= 16 => ColorDepth::Ansi16,
+            Some(colors) if colors >= 8 => ColorDepth::Ansi8,
+            Some(_) => ColorDepth::Mono,
+            None if term == "linux" => ColorDepth::Ansi16,
+            None => ColorDepth::Ansi256,
+        },
+        // e.g. the Windows console does not set TERM
+        None => ColorDepth::Ansi256,
+    }
+}
+
+// Look up the terminfo entry of a terminal and read its max_colors capability.
+// Terminals that do not list the capability return Some(-1), as they do not support colors.
+fn terminfo_colors(term: &str) -> Option<i32> {
+    let first = term.chars().next()?;
+
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list));
+    }
+    dirs.extend(
+        [
+            "/etc/terminfo",
+            "/lib/terminfo",
+            "/usr/share/terminfo",
+            "/usr/lib/terminfo",
+        ]
+        .iter()
+        .map(PathBuf::from),
+    );
+
+    dirs.iter()
+        // entries are grouped by their first letter, or its hex code on macOS
+        .flat_map(|dir| {
+            vec![
+                dir.join(first.to_string()).join(term),
+                dir.join(format!("{:x}", first as u32)).join(term),
+            ]
+        })
+        .find_map(|path| std::fs::read(path).ok())
+        .and_then(|data| parse_terminfo_colors(&data))
+}
+
+// Read max_colors from a compiled terminfo entry, see term(5)
+fn parse_terminfo_colors(data: &[u8]) -> Option<i32> {
+    const MAGIC_16BIT: i16 = 0o432;
+    const MAGIC_32BIT: i16 = 0o1036;
+    const MAX_COLORS: usize = 13;
+
+    let header = |i: usize| -> Option<i16> {
+        Some(i16::from_le_bytes([
+            *data.get(2 * i)?,
+            *data.get(2 * i + 1)?,
+        ]))
+    };
+
+    let number_size = match header(0)? {
+        MAGIC_16BIT => 2,
+        MAGIC_32BIT => 4,
+        _ => return None,
+    };
+    // sizes are never negative in a valid entry
+    let size = |i: usize| usize::try_from(header(i)?).ok();
+    let names_size = size(1)?;
+    let bool_count = size(2)?;
+    let num_count = size(3)?;
+
+    if num_count <= MAX_COLORS {
+        return Some(-1);
+    }
+
+    // the numbers section starts on an even byte
+    let mut offset = names_size.checked_add(bool_count)?.checked_add(12)?;
+    offset += offset % 2;
+    let start = offset.checked_add(MAX_COLORS * number_size)?;
+    let bytes = data.get(start..start.checked_add(number_size)?)?;
+
+    if number_size == 2 {
+        Some(i32::from(i16::from_le_bytes([bytes[0], bytes[1]])))
     } else {
-        false
+        Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
     }
 }
 
@@ -40,21 +193,415 @@ pub fn terminal_size() -> (u16, u16) {
     }
 }
 
-// Return a constant when running the tests
+/// Return a constant when running the tests
 #[cfg(test)]
 pub fn terminal_size() -> (u16, u16) {
     DEFAULT_TERM_SIZE
 }
 
+/// Returns the size of a terminal cell in pixels (width, height), if the terminal reports it.
+///
+/// It is taken from the window size of the terminal (`TIOCGWINSZ`). If that is not available,
+/// the terminal is asked for the cell size (`CSI 16 t`) and then for the window size in pixels
+/// (`CSI 14 t`). The result is cached after the first call.
+#[cfg(not(test))]
+pub fn cell_size() -> Option<(u16, u16)> {
+    *CELL_SIZE
+}
+
+/// Return None when running the tests
+#[cfg(test)]
+pub fn cell_size() -> Option<(u16, u16)> {
+    None
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn detect_cell_size() -> Option<(u16, u16)> {
+    let (columns, rows) = terminal_size();
+
+    #[cfg(unix)]
+    {
+        // SAFETY: winsize is plain data, which TIOCGWINSZ fills in
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+        if ok && ws.ws_xpixel > 0 && ws.ws_ypixel > 0 && ws.ws_col > 0 && ws.ws_row > 0 {
+            return Some((ws.ws_xpixel / ws.ws_col, ws.ws_ypixel / ws.ws_row));
+        }
+    }
+
+    if let Some(size) = query_terminal("\x1b[16t").and_then(|r| parse_size_report(&r, 6)) {
+        return Some(size);
+    }
+
+    let (width, height) = query_terminal("\x1b[14t").and_then(|r| parse_size_report(&r, 4))?;
+    if columns == 0 || rows == 0 || width < columns || height < rows {
+        return None;
+    }
+    Some((width / columns, height / rows))
+}
+
+#[cfg(not(test))]
+pub(crate) fn multiplexer() -> Multiplexer {
+    *MULTIPLEXER
+}
+
+// Escape sequences are written as they are when running the tests
+#[cfg(test)]
+pub(crate) fn multiplexer() -> Multiplexer {
+    Multiplexer::None
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn detect_multiplexer() -> Multiplexer {
+    if env::var_os("TMUX").is_some() {
+        Multiplexer::Tmux
+    } else if env::var_os("STY").is_some() {
+        Multiplexer::Screen
+    } else {
+        Multiplexer::None
+    }
+}
+
+// Name of the terminal, used to guess which graphics protocols it supports. Inside a multiplexer
+// this is the terminal outside of it, if it can be found, instead of TERM.
+pub(crate) fn terminal_name() -> Option<String> {
+    #[cfg(not(test))]
+    if let Some(name) = OUTER_TERMINAL.as_ref() {
+        return Some(name.clone());
+    }
+    env::var("TERM").ok()
+}
+
+// Ask tmux for the terminal of the attached client, e.g. `xterm-kitty`. Screen has no way to tell.
+#[cfg_attr(test, allow(dead_code))]
+fn detect_outer_terminal() -> Option<String> {
+    if detect_multiplexer() != Multiplexer::Tmux {
+        return None;
+    }
+
+    let output = std::process::Command::new("tmux")
+        .args(["display-message", "-p", "#{client_termname}"])
+        .output()
+        .ok()?;
+    let name = String::from_utf8(output.stdout).ok()?;
+    let name = name.trim();
+    if !output.status.success() || name.is_empty() {
+        return None;
+    }
+    Some(name.to_owned())
+}
+
+// Write an escape sequence meant for the terminal itself, like those of the graphics protocols.
+// Inside a multiplexer, it is wrapped so that it is passed through to the outer terminal. The
+// sequence has to be complete, as each one is wrapped on its own.
+pub(crate) fn write_passthrough(stdout: &mut dyn Write, sequence: &str) -> std::io::Result<()> {
+    match multiplexer() {
+        Multiplexer::None => stdout.write_all(sequence.as_bytes()),
+        multiplexer => stdout.write_all(&wrap_passthrough(sequence.as_bytes(), multiplexer)),
+    }
+}
+
+// Wrap a sequence in a DCS string which the multiplexer passes through. tmux needs every ESC in
+// it to be doubled, and `allow-passthrough` to be enabled since version 3.3. Screen limits the
+// length of the string, so longer sequences are split across several of them.
+fn wrap_passthrough(sequence: &[u8], multiplexer: Multiplexer) -> Vec<u8> {
+    let mut wrapped = Vec::with_capacity(sequence.len() + 16);
+    match multiplexer {
+        Multiplexer::None => wrapped.extend_from_slice(sequence),
+        Multiplexer::Tmux => {
+            wrapped.extend_from_slice(b"\x1bPtmux;");
+            for &byte in sequence {
+                if byte == 0x1b {
+                    wrapped.push(byte);
+                }
+                wrapped.push(byte);
+            }
+            wrapped.extend_from_slice(b"\x1b\\");
+        }
+        Multiplexer::Screen => {
+            let mut start = 0;
+            while start < sequence.len() {
+                let limit = sequence.len().min(start + SCREEN_CHUNK_SIZE - 4);
+                // screen ends the wrapper at the first string terminator, ESC \, so inner ones
+                // are split after their ESC. screen keeps an ESC right before its own terminator,
+                // and the next chunk starts with the backslash, so the terminal gets the whole
+                // terminator.
+                let terminator = sequence[start..sequence.len().min(limit + 1)]
+                    .windows(2)
+                    .position(|w| w == b"\x1b\\");
+                let end = match terminator {
+                    Some(i) => start + i + 1,
+                    None => {
+                        // any other escape is kept together with the byte following it
+                        let mut end = limit;
+                        while end < sequence.len() && end > start + 1 && sequence[end - 1] == 0x1b {
+                            end -= 1;
+                        }
+                        end
+                    }
+                };
+                wrapped.extend_from_slice(b"\x1bP");
+                wrapped.extend_from_slice(&sequence[start..end]);
+                wrapped.extend_from_slice(b"\x1b\\");
+                start = end;
+            }
+        }
+    }
+    wrapped
+}
+
+// Parse a size report from the terminal, `CSI kind ; height ; width t`, into (width, height)
+fn parse_size_report(response: &str, kind: u16) -> Option<(u16, u16)> {
+    let prefix = format!("\x1b[{};", kind);
+    let start = response.find(&prefix)? + prefix.len();
+    let end = start + response[start..].find('t')?;
+
+    let mut values = response[start..end].split(';').map(|v| v.parse::<u16>());
+    match (values.next(), values.next(), values.next()) {
+        (Some(Ok(height)), Some(Ok(width)), None) if width > 0 && height > 0 => {
+            Some((width, height))
+        }
+        _ => None,
+    }
+}
+
+// Write a query to the terminal and return what it responded. The query is followed by a request
+// for the primary device attributes, which every terminal answers. Once that answer arrives, all
+// the responses to the query have arrived too, so unsupported queries don't wait for a timeout.
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn query_terminal(query: &str) -> Option<String> {
+    query_with_device_attributes(query).map(|(response, _)| response)
+}
+
+// The primary device attributes of the terminal, `CSI ? ... c`, which list the features it
+// supports. Terminals which don't answer are given up on after the same timeout as other queries.
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn device_attributes() -> Option<String> {
+    query_with_device_attributes("").map(|(_, attributes)| attributes)
+}
+
+// Write a query followed by the request for the primary device attributes, and return the
+// response to the query and the device attributes separately
+#[cfg(unix)]
+#[cfg_attr(test, allow(dead_code))]
+fn query_with_device_attributes(query: &str) -> Option<(String, String)> {
+    use std::io::Write;
+
+    // SAFETY: isatty only inspects the file descriptors
+    if unsafe { libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0 } {
+        return None;
+    }
+
+    // turn off canonical mode and echo, so the response can be read as it comes, and hidden
+    // SAFETY: termios is plain data, which tcgetattr fills in
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+        return None;
+    }
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    let mut stdout = std::io::stdout();
+    let mut response = Vec::new();
+    let mut attributes = None;
+    if write!(stdout, "{}\x1b[c", query)
+        .and_then(|_| stdout.flush())
+        .is_ok()
+    {
+        while attributes.is_none() {
+            let mut fd = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let mut buf = [0u8; 256];
+            // SAFETY: the read is limited to the length of the buffer
+            let n = unsafe {
+                if libc::poll(&mut fd, 1, QUERY_TIMEOUT) <= 0 {
+                    break;
+                }
+                libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len())
+            };
+            if n <= 0 {
+                break;
+            }
+            response.extend_from_slice(&buf[..n as usize]);
+
+            if let Some(start) = device_attributes_start(&response) {
+                attributes = Some(response.split_off(start));
+            }
+        }
+    }
+
+    // SAFETY: original was filled in by tcgetattr
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+
+    let attributes = String::from_utf8(attributes?).ok()?;
+    Some((String::from_utf8(response).ok()?, attributes))
+}
+
+#[cfg(not(unix))]
+#[cfg_attr(test, allow(dead_code))]
+fn query_with_device_attributes(_query: &str) -> Option<(String, String)> {
+    None
+}
+
+// Position of the response to the primary device attributes query, `CSI ? ... c`, if all of it
+// was received
+#[cfg_attr(not(unix), allow(dead_code))]
+fn device_attributes_start(response: &[u8]) -> Option<usize> {
+    let start = response.windows(3).rposition(|w| w == b"\x1b[?")?;
+    match response[start + 3..].split_last() {
+        Some((b'c', params)) if params.iter().all(|b| b.is_ascii_digit() || *b == b';') => {
+            Some(start)
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_truecolor() {
-        env::set_var("COLORTERM", "truecolor");
-        assert!(truecolor_available());
-        env::set_var("COLORTERM", "");
-        assert!(!truecolor_available());
+    fn test_wrap_passthrough() {
+        let sequence = b"\x1b_Ga=d\x1b\\";
+        assert_eq!(wrap_passthrough(sequence, Multiplexer::None), sequence);
+        assert_eq!(
+            wrap_passthrough(sequence, Multiplexer::Tmux),
+            b"\x1bPtmux;\x1b\x1b_Ga=d\x1b\x1b\\\x1b\\"
+        );
+
+        let long = vec![b'a'; 1000];
+        let wrapped = wrap_passthrough(&long, Multiplexer::Screen);
+        assert_eq!(wrapped.len(), 1000 + 2 * 4);
+        assert_eq!(&wrapped[..2], b"\x1bP");
+        assert_eq!(&wrapped[766..770], b"\x1b\\\x1bP");
+
+        // inner terminators close the wrapper after their ESC, and reopen it for the backslash
+        assert_eq!(
+            wrap_passthrough(sequence, Multiplexer::Screen),
+            b"\x1bP\x1b_Ga=d\x1b\x1b\\\x1bP\\\x1b\\"
+        );
+
+        // the chunk boundary falls in the middle of a terminator
+        let mut escaped = vec![b'a'; 763];
+        escaped.extend_from_slice(b"\x1b\\bb");
+        let wrapped = wrap_passthrough(&escaped, Multiplexer::Screen);
+        assert_eq!(wrapped.len(), escaped.len() + 2 * 4);
+        assert_eq!(&wrapped[764..], b"a\x1b\x1b\\\x1bP\\bb\x1b\\");
+
+        // the chunk boundary falls between another escape and the byte after it
+        let mut escaped = vec![b'a'; 763];
+        escaped.extend_from_slice(b"\x1b_bb");
+        let wrapped = wrap_passthrough(&escaped, Multiplexer::Screen);
+        assert_eq!(wrapped.len(), escaped.len() + 2 * 4);
+        assert_eq!(&wrapped[764..], b"a\x1b\\\x1bP\x1b_bb\x1b\\");
+    }
+
+    #[test]
+    fn test_color_depth() {
+        let colors = |n: i32| move |_: &str| Some(n);
+        let unknown = |_: &str| None;
+
+        assert_eq!(
+            color_depth_from(false, Some("truecolor"), Some("xterm"), colors(8)),
+            ColorDepth::Truecolor
+        );
+        assert_eq!(
+            color_depth_from(true, Some("truecolor"), Some("xterm"), colors(8)),
+            ColorDepth::Mono
+        );
+        assert_eq!(
+            color_depth_from(false, Some(""), Some("dumb"), colors(256)),
+            ColorDepth::Mono
+        );
+        assert_eq!(
+            color_depth_from(false, None, Some("xterm-256color"), colors(256)),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            color_depth_from(false, None, Some("xterm"), colors(8)),
+            ColorDepth::Ansi8
+        );
+        assert_eq!(
+            color_depth_from(false, None, Some("vt100"), colors(-1)),
+            ColorDepth::Mono
+        );
+        assert_eq!(
+            color_depth_from(false, None, Some("linux"), unknown),
+            ColorDepth::Ansi16
+        );
+        assert_eq!(
+            color_depth_from(false, None, None, unknown),
+            ColorDepth::Ansi256
+        );
+    }
+
+    // Build a compiled terminfo entry with the given numbers
+    fn terminfo_entry(magic: i16, numbers: &[i32]) -> Vec<u8> {
+        let number_size = if magic == 0o432 { 2 } else { 4 };
+        let names = b"test|test\0";
+        let mut data = Vec::new();
+        for h in &[magic, names.len() as i16, 1, numbers.len() as i16, 0, 0] {
+            data.extend_from_slice(&h.to_le_bytes());
+        }
+        data.extend_from_slice(names);
+        // a single boolean, followed by padding
+        data.extend_from_slice(&[1, 0]);
+        for n in numbers {
+            data.extend_from_slice(&n.to_le_bytes()[..number_size]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_terminfo_colors() {
+        let mut numbers = vec![-1; 15];
+        numbers[13] = 256;
+        assert_eq!(
+            parse_terminfo_colors(&terminfo_entry(0o432, &numbers)),
+            Some(256)
+        );
+
+        numbers[13] = 1 << 24;
+        assert_eq!(
+            parse_terminfo_colors(&terminfo_entry(0o1036, &numbers)),
+            Some(1 << 24)
+        );
+
+        assert_eq!(
+            parse_terminfo_colors(&terminfo_entry(0o432, &[80, 8])),
+            Some(-1)
+        );
+        assert_eq!(parse_terminfo_colors(&[0, 1, 2]), None);
+
+        // a negative size of the names section
+        let mut entry = terminfo_entry(0o432, &numbers);
+        entry[2..4].copy_from_slice(&(-2i16).to_le_bytes());
+        assert_eq!(parse_terminfo_colors(&entry), None);
+    }
+
+    #[test]
+    fn test_parse_size_report() {
+        assert_eq!(parse_size_report("\x1b[6;20;10t", 6), Some((10, 20)));
+        assert_eq!(parse_size_report("\x1b[4;480;800t", 4), Some((800, 480)));
+        assert_eq!(parse_size_report("\x1b[4;480;800t", 6), None);
+        assert_eq!(parse_size_report("\x1b[6;0;10t", 6), None);
+        assert_eq!(parse_size_report("\x1b[6;20t", 6), None);
+    }
+
+    #[test]
+    fn test_device_attributes_start() {
+        assert_eq!(
+            device_attributes_start(b"\x1b[6;20;10t\x1b[?62;4c"),
+            Some(10)
+        );
+        assert_eq!(device_attributes_start(b"\x1b[?62;4c"), Some(0));
+        assert_eq!(device_attributes_start(b"\x1b[6;20;10t\x1b[?62;4"), None);
+        assert_eq!(device_attributes_start(b"\x1b[?1;0;256S"), None);
     }
 }